@@ -0,0 +1,109 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Keep the original file around while it's being overwritten with
+//! candidates.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, Once};
+
+static PENDING: Mutex<Pending> = Mutex::new(Pending { files: Vec::new(), interrupted: false });
+static HANDLER: Once = Once::new();
+
+struct Pending {
+    /// Files that need to be put back if we're interrupted.
+    files: Vec<(PathBuf, Vec<u8>)>,
+    /// Set once they have been, so nothing overwrites them again before
+    /// we exit.
+    interrupted: bool,
+}
+
+fn pending() -> MutexGuard<'static, Pending> {
+    // Nothing panics while holding the lock, but don't make matters worse.
+    PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub(crate) struct Backup {
+    path: PathBuf,
+    original: Vec<u8>,
+}
+
+impl Backup {
+    /// Copy `path` to `path.orig` and arrange for it to be restored on Ctrl-C,
+    /// once `handle_interrupts` has been called.
+    pub(crate) fn new(path: &Path) -> std::io::Result<Backup> {
+        let original = std::fs::read(path)?;
+        std::fs::write(orig_path(path), &original)?;
+
+        pending().files.push((path.to_owned(), original.clone()));
+
+        Ok(Backup { path: path.to_owned(), original })
+    }
+
+    pub(crate) fn restore(&self) -> std::io::Result<()> {
+        std::fs::write(&self.path, &self.original)
+    }
+}
+
+impl Drop for Backup {
+    fn drop(&mut self) {
+        pending().files.retain(|(path, _)| *path != self.path);
+    }
+}
+
+/// On Ctrl-C, kill any running commands, put back the files being reduced,
+/// and exit. The handler is process-wide, so it's left to the program to
+/// install; without it, commands run with a timeout are in their own
+/// process group and won't see the Ctrl-C.
+pub fn handle_interrupts() {
+    HANDLER.call_once(|| {
+        if let Err(e) = ctrlc::set_handler(restore_all) {
+            eprintln!("rust-reduce: couldn't install Ctrl-C handler: {}", e);
+        }
+    });
+}
+
+/// Write `contents` to `path`, unless we've been interrupted, in which case
+/// the original may already have been put back.
+pub(crate) fn write<C: AsRef<[u8]>>(path: &Path, contents: C) -> std::io::Result<()> {
+    let pending = pending();
+    if pending.interrupted {
+        return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "interrupted"));
+    }
+    std::fs::write(path, contents)
+}
+
+pub(crate) fn orig_path(path: &Path) -> PathBuf {
+    let mut orig = path.as_os_str().to_owned();
+    orig.push(".orig");
+    PathBuf::from(orig)
+}
+
+fn restore_all() {
+    // Held until we exit, so no candidate gets written after this.
+    let mut pending = pending();
+    pending.interrupted = true;
+    crate::predicate::kill_running();
+    for (path, original) in pending.files.iter() {
+        match std::fs::write(path, original) {
+            Ok(()) => eprintln!("rust-reduce: interrupted, restored {}", path.display()),
+            Err(e) => eprintln!("rust-reduce: interrupted, couldn't restore {} ({}), see {}",
+                                path.display(), e, orig_path(path).display()),
+        }
+    }
+    std::process::exit(130);
+}
@@ -1,18 +1,30 @@
 use std::path::{Path, PathBuf};
 use std::ffi::{OsString};
+use std::time::Duration;
 use std::process::Command;
 use clap::clap_app;
 
-use rust_reduce::Runnable;
+use rust_reduce::{handle_interrupts, Options, Reducer, Runnable};
+use rust_reduce::manifest::Manifests;
+use rust_reduce::transforms::eval_cfg::CfgSet;
+use rust_reduce::predicate::{ExitPredicate, OutputPredicate, Predicate, Stream};
+use regex::Regex;
 use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
 
 #[derive(Debug, Deserialize)]
 struct Config {
+    package: Option<PackageConfig>,
     workspace: Option<WorkspaceConfig>,
     lib: Option<LibConfig>,
     bin: Option<Vec<BinConfig>>,
 }
 
+#[derive(Debug, Deserialize)]
+struct PackageConfig {
+    name: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct WorkspaceConfig {
     members: Vec<String>,
@@ -20,116 +32,349 @@ struct WorkspaceConfig {
 
 #[derive(Debug, Deserialize)]
 struct LibConfig {
-    name: String,
-    path: String,
+    name: Option<String>,
+    path: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct BinConfig {
     name: String,
-    path: String,
+    path: Option<String>,
 }
 
 fn main() {
     let matches = clap_app!(("cargo-reduce") =>
         (version: clap::crate_version!())
-        (@arg FIND: * "Text indicating success.")
-        (@arg CMD: * "Command to run.")
-        (@arg ARGS: * ... "Arguments to the command to run E.g. cargo run / cargo test / cargo build.")
+        (@arg DDMIN: --ddmin "Remove items in ever smaller chunks before trying them one by one. Much faster on large inputs with lots of dead code.")
+        (@arg SPLIT_MODULES: --("split-modules") "Split the result back into the files its modules were originally loaded from, deleting the ones that are no longer needed. With --output, the files are written into that directory instead.")
+        (@arg OUTPUT: -o --output +takes_value "Write the reduced file here and restore the original, instead of overwriting the original.")
+        (@arg CACHE: --cache +takes_value "Record whether each candidate was interesting in this file, and skip candidates already recorded there, e.g. by an earlier run that was interrupted.")
+        (@arg CHECKPOINT: --checkpoint +takes_value "Every now and then, save the smallest interesting version so far and how far each pass has got to this file. It's removed once the reduction is done.")
+        (@arg RESUME: --resume requires[CHECKPOINT] "Carry on from the --checkpoint file, if there is one, instead of starting over.")
+        (@arg PASSES: --passes +takes_value "Only run these passes, in this order, separated by commas. See --list-passes.")
+        (@arg SKIP: --skip +takes_value "Don't run these passes, separated by commas, e.g. `remove-doc` when reducing a rustdoc bug.")
+        (@arg KEEP_ATTRS: --("keep-attrs") +takes_value "Never remove attributes with these paths, separated by commas, e.g. `repr,inline`.")
+        (@arg ONLY_ATTRS: --("only-attrs") +takes_value "Only try removing attributes with these paths, separated by commas, e.g. `cfg_attr,allow`. By default, any attribute but `cfg` is tried.")
+        (@arg EVAL_CFG: --("eval-cfg") "Remove code that's configured out and expand #[cfg_attr]s that apply, using the cfgs rustc sets for the host, or for --cfg-target, along with --cfg and --features. Implied by those.")
+        (@arg CFG: --cfg +takes_value +multiple number_of_values(1) "Set a cfg for --eval-cfg, like `rustc --cfg`, e.g. `test` or `feature=\"std\"`. May be given more than once.")
+        (@arg FEATURES: --features +takes_value "Enable these features for --eval-cfg, separated by commas.")
+        (@arg CFG_TARGET: --("cfg-target") +takes_value "Use the cfgs rustc sets for this target triple for --eval-cfg, instead of those for the host.")
+        (@arg LIST_PASSES: --("list-passes") "List the passes, in the order they're run by default, and exit.")
+        (@arg MAX_ROUNDS: --("max-rounds") +takes_value "Stop after this many rounds of all passes. By default, rounds are repeated until one doesn't make the file any smaller.")
+        (@arg JOBS: -j --jobs +takes_value "Number of candidates to test concurrently, each in its own copy of the crate. Only prune, remove-attrs, remove-stmts, simplify-exprs, reduce-signatures and reduce-generics test more than one at a time.")
+        (@arg MANIFESTS: --manifests "Also reduce the Cargo.toml files: try removing workspace members, dependencies, features and profiles. They're backed up with the `.orig` suffix first. With --output, the originals are restored and the reduced ones are written next to them with the `.min` suffix.")
+        (@arg PACKAGE: -p --package +takes_value +multiple number_of_values(1) "Only reduce entry points of this package. May be given more than once.")
+        (@arg TARGET: -t --target +takes_value +multiple number_of_values(1) "Only reduce this lib or bin target. May be given more than once, in which case targets are reduced in the given order.")
+        (@arg REGEX: --regex "Treat FIND and the --also/--not patterns as regular expressions instead of plain text.")
+        (@arg ALSO: --also +takes_value +multiple number_of_values(1) "Another pattern that must be found as well as FIND. May be given more than once.")
+        (@arg ANY: --any "Only one of FIND and the --also patterns needs to be found.")
+        (@arg NOT: --not +takes_value +multiple number_of_values(1) "A pattern that must not be found, e.g. to avoid drifting into a different error. May be given more than once.")
+        (@arg STREAM: --stream +takes_value possible_values(&["stdout", "stderr", "both"]) "Which output to look for patterns in. Defaults to both.")
+        (@arg EXIT_CODE: --("exit-code") +takes_value conflicts_with[SIGNAL DIFFERS_FROM HANG] "Also require the command to exit with this code.")
+        (@arg SIGNAL: --signal +takes_value conflicts_with[DIFFERS_FROM HANG] "Also require the command to be killed by this signal, e.g. SIGSEGV or SIGABRT, or by any signal with `any`. Only useful if the command runs rustc itself: cargo exits with code 101 when rustc crashes, so look for e.g. `(signal: 11, SIGSEGV` in its output instead.")
+        (@arg DIFFERS_FROM: --("differs-from") +takes_value conflicts_with[HANG] "Also require the command to exit differently from this other command, split on whitespace, e.g. `cargo run --release`.")
+        (@arg TIMEOUT: --timeout +takes_value "Kill the command, and anything it started, after this many seconds. Unless --hang is given, that makes the candidate uninteresting.")
+        (@arg HANG: --hang requires[TIMEOUT] "Also require the command to time out, e.g. to reduce a compiler hang.")
+        (@arg FIND: required_unless[LIST_PASSES] "Text indicating success. Use \"\" to only look at the exit status.")
+        (@arg CMD: required_unless[LIST_PASSES] "Command to run.")
+        (@arg ARGS: ... required_unless[LIST_PASSES] "Arguments to the command to run E.g. cargo run / cargo test / cargo build.")
         (after_help: "`cargo-reduce` will try to make the source file smaller by interpreting it as valid Rust code and intelligently removing parts of the code. After each removal, the given command will be run with reduced code.
 
-The original file will be overwritten with the smallest interesting reduced version, if found. This happens while `rust-reduce` is running. The original file will be backed up with the `.orig` suffix. If `rustfmt` is found, it will be used to clean up the output.
+The original file will be overwritten with the smallest interesting reduced version, if found. This happens while `cargo-reduce` is running. The original file will be backed up with the `.orig` suffix, and restored if `cargo-reduce` is interrupted with Ctrl-C. With `--output`, the original file is restored at the end and the reduced version is written to the given path instead. If `rustfmt` is found, it will be used to clean up the output.
 
 The original file may refer to modules in different files, these will be inlined and reduced along with the main file.")
     ).get_matches();
-    //TODO recurse?
-    let decoded: Config = toml::from_str(&std::fs::read_to_string("Cargo.toml")
-        .expect("Cargo.toml file not found"))
-        .expect("Can't parse Cargo.toml");
-    if let Some(cfg) = decoded.workspace {
-        println!("Found workspace: {:?}", &cfg.members);
-    }
-
-
-    //let mut h = std::collections::HashMap::new();
-    //h.insert("a", "b");
-    //h.insert("c", "d");
-    //panic!("does it break");
 
-    let find = matches.value_of_lossy("FIND").expect("string to search for").to_owned();
+    let list = |arg| matches.value_of(arg).map_or(Vec::new(), |list: &str| list.split(',').map(str::trim).collect::<Vec<_>>());
+    let options = Options {
+        ddmin: matches.is_present("DDMIN"),
+        jobs: matches.value_of("JOBS")
+            .map(|jobs| jobs.parse().expect("--jobs takes a number"))
+            .unwrap_or(1),
+        max_rounds: matches.value_of("MAX_ROUNDS")
+            .map(|rounds| rounds.parse().expect("--max-rounds takes a number")),
+        output: matches.value_of_os("OUTPUT").map(PathBuf::from),
+        split_modules: matches.is_present("SPLIT_MODULES"),
+        cache: matches.value_of_os("CACHE").map(PathBuf::from),
+        checkpoint: matches.value_of_os("CHECKPOINT").map(PathBuf::from),
+        resume: matches.is_present("RESUME"),
+        keep_attrs: list("KEEP_ATTRS").into_iter().map(String::from).collect(),
+        only_attrs: list("ONLY_ATTRS").into_iter().map(String::from).collect(),
+        cfg: cfg_set(&matches),
+    };
+    if matches.is_present("LIST_PASSES") {
+        for pass in rust_reduce::transforms::default_passes(&options) {
+            println!("{:<22}{}", pass.name(), pass.description());
+        }
+        return;
+    }
+    let (only, skip) = (list("PASSES"), list("SKIP"));
+    let passes = || rust_reduce::transforms::select_passes(&only, &skip, &options).unwrap_or_else(|e| {
+        eprintln!("cargo-reduce: {}", e);
+        std::process::exit(1);
+    });
+    // Check them before doing anything else.
+    passes();
+    let pattern = |text: &str| {
+        let pattern = if matches.is_present("REGEX") {
+            text.to_owned()
+        } else {
+            regex::escape(text)
+        };
+        Regex::new(&pattern).unwrap_or_else(|e| {
+            eprintln!("cargo-reduce: invalid pattern: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let mut patterns = vec![pattern(&matches.value_of_lossy("FIND").expect("string to search for"))];
+    patterns.extend(matches.values_of_lossy("ALSO").unwrap_or_default().iter().map(|text| pattern(text)));
+    let output = OutputPredicate {
+        patterns,
+        forbidden: matches.values_of_lossy("NOT").unwrap_or_default().iter().map(|text| pattern(text)).collect(),
+        any: matches.is_present("ANY"),
+        stream: matches.value_of("STREAM").map_or(Stream::Both, |stream| stream.parse().expect("validated")),
+    };
+    let exit = if let Some(code) = matches.value_of("EXIT_CODE") {
+        ExitPredicate::Code(code.parse().expect("--exit-code takes a number"))
+    } else if let Some(signal) = matches.value_of("SIGNAL") {
+        ExitPredicate::signal(signal).unwrap_or_else(|e| {
+            eprintln!("cargo-reduce: {}", e);
+            std::process::exit(1);
+        })
+    } else if let Some(other) = matches.value_of("DIFFERS_FROM") {
+        ExitPredicate::DiffersFrom(other.split_whitespace().map(OsString::from).collect())
+    } else if matches.is_present("HANG") {
+        ExitPredicate::TimesOut
+    } else {
+        ExitPredicate::Any
+    };
+    let timeout = matches.value_of("TIMEOUT")
+        .map(|secs| Duration::from_secs_f64(secs.parse().expect("--timeout takes a number of seconds")));
+    let find = Predicate { exit, output: Some(output), timeout };
     let mut cmd = vec![matches.value_of_os("CMD").expect("validated").to_owned()];
     let iter = matches.values_of_os("ARGS").expect("validated").map(ToOwned::to_owned);
     cmd.extend(iter);
 
-    let runnable = Standard::new(cmd, find.to_string(),
-                                 std::env::current_dir().unwrap());
+    let root_dir = std::env::current_dir().unwrap();
+    handle_interrupts();
+
+    // Not tied to any particular source file.
+    let workspace = Standard::new(cmd.clone(), find.clone(), root_dir.clone(), root_dir.join("Cargo.toml"));
+    let manifests = if matches.is_present("MANIFESTS") {
+        if let Err(msg) = workspace.run() {
+            eprintln!("cargo-reduce: run with initial input did not indicate success: {}", msg);
+            std::process::exit(1);
+        }
+        let mut manifests = Manifests::load(&root_dir)
+            .unwrap_or_else(|e| {
+                eprintln!("cargo-reduce: couldn't load Cargo.toml files: {}", e);
+                std::process::exit(1);
+            })
+            .progress(|msg| println!("{}", msg));
+        println!("Removing workspace members");
+        if let Err(e) = manifests.reduce_members(&workspace) {
+            eprintln!("cargo-reduce: {}", e);
+            std::process::exit(1);
+        }
+        Some(manifests)
+    } else {
+        None
+    };
+
+    let mut entries = Vec::new();
+    if let Err(e) = entry_points(root_dir.clone(), &mut entries) {
+        eprintln!("cargo-reduce: {}", e);
+        std::process::exit(1);
+    }
+    println!("Found entry points:");
+    for entry in &entries {
+        println!("    {}", entry);
+    }
+
+    if let Some(packages) = matches.values_of("PACKAGE") {
+        let packages: Vec<_> = packages.collect();
+        entries.retain(|entry| packages.contains(&&*entry.package));
+    }
+    if let Some(targets) = matches.values_of("TARGET") {
+        entries = targets.flat_map(|target| entries.iter().filter(move |entry| entry.target == target))
+            .cloned()
+            .collect();
+    }
+    if entries.is_empty() {
+        eprintln!("cargo-reduce: no entry points selected");
+        std::process::exit(1);
+    }
+    if entries.len() > 1 && options.output.is_some() {
+        eprintln!("cargo-reduce: --output needs a single entry point, pick one with --target");
+        std::process::exit(1);
+    }
 
-    rust_reduce::reduce( runnable);
+    for entry in entries {
+        println!("Reducing {}", entry);
+        let runnable = Standard::new(cmd.clone(), find.clone(), root_dir.clone(), entry.path);
+        let reducer = Reducer::new(runnable)
+            .options(options.clone())
+            .passes(passes())
+            .progress(|msg| println!("{}", msg));
+        if let Err(e) = reducer.reduce() {
+            eprintln!("cargo-reduce: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(manifests) = manifests {
+        if let Err(e) = reduce_manifests(manifests, &workspace, options.output.is_some()) {
+            eprintln!("cargo-reduce: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Everything `--manifests` does after the entry points are reduced, writing
+/// the results to `Cargo.toml.min` if `write_min`.
+fn reduce_manifests(mut manifests: Manifests, workspace: &Standard, write_min: bool) -> std::io::Result<()> {
+    println!("Removing dependencies");
+    manifests.reduce_dependencies(workspace)?;
+    println!("Removing features");
+    manifests.reduce_features(workspace)?;
+    println!("Removing profiles");
+    manifests.reduce_profiles(workspace)?;
+    if write_min {
+        manifests.write_min()?;
+    }
+    Ok(())
+}
+
+/// The configuration for the `eval-cfg` pass, if it's to do anything.
+fn cfg_set(matches: &clap::ArgMatches) -> Option<CfgSet> {
+    if !["EVAL_CFG", "CFG", "FEATURES", "CFG_TARGET"].iter().any(|arg| matches.is_present(arg)) {
+        return None;
+    }
+    let cfgs = matches.values_of("CFG").into_iter().flatten();
+    let cfg = CfgSet::from_args(matches.value_of("CFG_TARGET"), cfgs, matches.value_of("FEATURES"))
+        .unwrap_or_else(|e| {
+            eprintln!("cargo-reduce: {}", e);
+            std::process::exit(1);
+        });
+    Some(cfg)
 }
 
 struct Standard {
     file: PathBuf,
     root_dir: PathBuf,
     cmd: Vec<OsString>,
-    /// String to minimise while keeping in output.
-    find: String
+    /// What to keep finding in the output while minimising.
+    find: Predicate
+}
+
+/// A lib or bin target's root source file.
+#[derive(Clone, Debug)]
+struct EntryPoint {
+    package: String,
+    target: String,
+    path: PathBuf,
+}
+
+impl std::fmt::Display for EntryPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{} ({})", self.package, self.target, self.path.display())
+    }
 }
 
 /// Recursively list all entry points. (for now excluding examples)
-fn entry_points(base_path: PathBuf, results: &mut Vec<PathBuf>) {
+fn entry_points(base_path: PathBuf, results: &mut Vec<EntryPoint>) -> Result<(), String> {
     let cargo_toml = base_path.join("Cargo.toml");
-    let cargo_toml : Config = toml::from_str(&std::fs::read_to_string(cargo_toml)
-        .unwrap()).unwrap();
+    let text = std::fs::read_to_string(&cargo_toml)
+        .map_err(|e| format!("couldn't read {}: {}", cargo_toml.display(), e))?;
+    let cargo_toml : Config = toml::from_str(&text)
+        .map_err(|e| format!("couldn't parse {}: {}", cargo_toml.display(), e))?;
 
-    let initial = results.len();
+    if let Some(package) = cargo_toml.package {
+        let mut push = |target: String, path: PathBuf| results.push(EntryPoint {
+            package: package.name.clone(),
+            target,
+            path,
+        });
 
-    if let Some(lib) = cargo_toml.lib {
-        results.push(base_path.join(lib.path));
-    }
+        // Same defaults as cargo's target auto-discovery.
+        let lib_name = package.name.replace('-', "_");
+        match cargo_toml.lib {
+            Some(lib) => push(lib.name.unwrap_or(lib_name),
+                              base_path.join(lib.path.as_deref().unwrap_or("src/lib.rs"))),
+            None if base_path.join("src/lib.rs").exists() =>
+                push(lib_name, base_path.join("src/lib.rs")),
+            None => {},
+        }
 
-    if let Some(bin) = cargo_toml.bin {
-        for b in bin {
-            results.push(base_path.join(b.path));
+        match cargo_toml.bin {
+            Some(bin) => for b in bin {
+                let path = match b.path {
+                    Some(path) => base_path.join(path),
+                    None if bin_dir(&base_path).join(format!("{}.rs", b.name)).exists() =>
+                        bin_dir(&base_path).join(format!("{}.rs", b.name)),
+                    None => base_path.join("src/main.rs"),
+                };
+                push(b.name, path);
+            },
+            None => {
+                if base_path.join("src/main.rs").exists() {
+                    push(package.name.clone(), base_path.join("src/main.rs"));
+                }
+                if let Ok(dir) = std::fs::read_dir(bin_dir(&base_path)) {
+                    let mut bins: Vec<_> = dir.filter_map(Result::ok)
+                        .map(|entry| entry.path())
+                        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+                        .collect();
+                    bins.sort();
+                    for path in bins {
+                        let name = path.file_stem().expect("file has a name").to_string_lossy().into_owned();
+                        push(name, path);
+                    }
+                }
+            },
         }
     }
 
     if let Some(workspace) = cargo_toml.workspace {
-        for krate in workspace.members {
-            entry_points(base_path.join(krate), results )
+        for member in workspace.members {
+            let dirs = rust_reduce::manifest::expand_member(&base_path, &member)
+                .map_err(|e| format!("couldn't expand workspace member {}: {}", member, e))?;
+            for dir in dirs {
+                entry_points(dir, results)?;
+            }
         }
     }
+    Ok(())
+}
 
-    if initial == results.len()
-    {
-        //Assume main
-        results.push(base_path.join("src/main.rs"));
+/// The manifests and Rust sources under `dir`, leaving out the crate's own
+/// `target` and `.git`.
+fn crate_files(dir: &Path, top: bool, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name();
+        let path = entry.path();
+        if top && (name == "target" || name == ".git") {
+            continue;
+        }
+        if path.is_dir() {
+            crate_files(&path, false, files);
+        } else if name == "Cargo.toml" || path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
     }
 }
 
-impl Standard {
-
-    fn new(cmd: Vec<OsString>, find: String, root_dir: PathBuf) -> Standard {
-        //todo: for now minimise first thing.
-
-        let mut results = Vec::new();
-        entry_points(root_dir.clone(), &mut results);
-
-//        let file: OsString = if Some(lib) = config.lib {
-//            OsString::from(lib.path)
-//
-//        } else if let Some(workspace) = config.worspace {
-//            let cargo_toml = format!("{}/Cargo.toml", workspace.members[0]);
-//
-//            let cargo_toml = toml::from_str(&std::fs::read_to_string(cargo_toml)
-//                .unwrap()).unwrap();
-//        } else {
-//            assert!(root_dir.join("src/main.rs").exists());
-//            OsString::from("src/main.rs")
-//        };
-        println!("Found entry points: {:#?}, picking first", results);
-
+fn bin_dir(base_path: &Path) -> PathBuf {
+    base_path.join("src/bin")
+}
 
-        Standard { file: results[0].clone(), cmd, find, root_dir }
+impl Standard {
+    fn new(cmd: Vec<OsString>, find: Predicate, root_dir: PathBuf, file: PathBuf) -> Standard {
+        Standard { file, cmd, find, root_dir }
     }
 }
 
@@ -142,26 +387,36 @@ impl Runnable for Standard {
         &self.file
     }
 
-    fn run(&self) -> Result<(), String> {
-        let (cmd, args) = self.cmd.split_first().expect("validated");
-        let out = Command::new(cmd)
-            .args(args)
-            .current_dir(&self.root_dir)
-            .output();
-        if let Ok(out) = out {
-            if String::from_utf8_lossy(&out.stdout).contains(&self.find) {
-                return Ok(());
-            }
-            if String::from_utf8_lossy(&out.stderr).contains(&self.find)
-            {
-                return Ok(());
+    /// Includes the rest of the crate, so outcomes cached before a manifest
+    /// or another module changed aren't reused.
+    fn describe(&self) -> String {
+        let mut files = Vec::new();
+        crate_files(&self.root_dir, true, &mut files);
+        files.sort();
+        let mut hasher = Sha256::new();
+        for file in files.iter().filter(|file| **file != self.file) {
+            let path = file.to_string_lossy();
+            let contents = std::fs::read(file).unwrap_or_default();
+            hasher.update((path.len() as u64).to_le_bytes());
+            hasher.update(path.as_bytes());
+            hasher.update((contents.len() as u64).to_le_bytes());
+            hasher.update(&contents);
+        }
+        format!("{:?} {:?} {:?} {:?} {:x}", self.root_dir, self.file, self.cmd, self.find, hasher.finalize())
+    }
+
+    fn run_in(&self, root: &Path, _path: &Path) -> Result<(), String> {
+        self.find.run(&self.cmd, |cmd| {
+            let (cmd, args) = cmd.split_first().expect("validated");
+            let mut command = Command::new(cmd);
+            command.args(args)
+                .current_dir(root);
+            if root != self.root_dir {
+                // Copies get their own target dir, even if the user set one.
+                command.env("CARGO_TARGET_DIR", root.join("target"));
             }
-            Err(format!("\nCould not find `{}` in:\nout:\n{}\nerr:\n{}",
-                               &self.find, String::from_utf8_lossy(&out.stdout),
-                               String::from_utf8_lossy(&out.stderr)))
-        } else {
-            println!("Couldn't find program to execute");
-            Err(format!("Failed to execute: {:#?}", out)) }
+            command
+        })
     }
 }
 
@@ -176,7 +431,7 @@ mod tests {
     fn home() -> PathBuf {
         Path::new(&std::env::var_os("HOME").unwrap_or_else(|| {
             let mut s = std::env::var_os("HOMEDRIVE").expect("HOMEDRIVE");
-            s.push(&std::env::var_os("HOMEPATH").expect("HOMEPATH"));
+            s.push(std::env::var_os("HOMEPATH").expect("HOMEPATH"));
             s
         })).to_owned()
     }
@@ -185,8 +440,9 @@ mod tests {
     fn test_find() {
         let r = Standard::new(vec![OsString::from("echo"),
                                    OsString::from("needle")],
-                              "needle".to_string(),
-                              std::env::current_dir().unwrap());
+                              OutputPredicate::contains("needle").into(),
+                              std::env::current_dir().unwrap(),
+                              PathBuf::from("src/lib.rs"));
         assert_eq!(Ok(()), r.run());
     }
 
@@ -194,25 +450,224 @@ mod tests {
     fn test_not_find() {
         let r = Standard::new(vec![OsString::from("echo"),
                                    OsString::from("haystack")],
-                              "needle".to_string(),
-                              std::env::current_dir().unwrap());
+                              OutputPredicate::contains("needle").into(),
+                              std::env::current_dir().unwrap(),
+                              PathBuf::from("src/lib.rs"));
         assert!(r.run().is_err());
     }
 
+    fn echo(text: &str, find: OutputPredicate) -> Result<(), String> {
+        Standard::new(vec![OsString::from("echo"), OsString::from(text)],
+                      find.into(),
+                      std::env::current_dir().unwrap(),
+                      PathBuf::from("src/lib.rs")).run()
+    }
+
+    #[test]
+    fn test_patterns() {
+        let find = |patterns: &[&str], forbidden: &[&str], any, stream| OutputPredicate {
+            patterns: patterns.iter().map(|p| Regex::new(p).unwrap()).collect(),
+            forbidden: forbidden.iter().map(|p| Regex::new(p).unwrap()).collect(),
+            any,
+            stream,
+        };
+        let text = "error[E0599]: no method named `nee` found";
+
+        assert_eq!(Ok(()), echo(text, find(&[r"error\[E\d+\]", "nee"], &[], false, Stream::Both)));
+        assert!(echo(text, find(&[r"error\[E\d+\]", "haystack"], &[], false, Stream::Both)).is_err());
+        assert_eq!(Ok(()), echo(text, find(&[r"error\[E\d+\]", "haystack"], &[], true, Stream::Both)));
+        assert!(echo(text, find(&["nee"], &[r"E0599"], false, Stream::Both)).is_err());
+        assert_eq!(Ok(()), echo(text, find(&["nee"], &[r"E0433"], false, Stream::Stdout)));
+        assert!(echo(text, find(&["nee"], &[], false, Stream::Stderr)).is_err());
+    }
+
+    fn sh(script: &str, exit: ExitPredicate) -> Result<(), String> {
+        sh_with(script, Predicate { exit, output: Some(OutputPredicate::contains("needle")), timeout: None })
+    }
+
+    fn sh_with(script: &str, predicate: Predicate) -> Result<(), String> {
+        Standard::new(vec![OsString::from("sh"), OsString::from("-c"), OsString::from(script)],
+                      predicate,
+                      std::env::current_dir().unwrap(),
+                      PathBuf::from("src/lib.rs")).run()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_exit_status() {
+        assert_eq!(Ok(()), sh("echo needle; exit 3", ExitPredicate::Code(3)));
+        assert!(sh("echo needle; exit 4", ExitPredicate::Code(3)).is_err());
+        assert!(sh("echo haystack; exit 3", ExitPredicate::Code(3)).is_err());
+
+        let segv = ExitPredicate::signal("SIGSEGV").unwrap();
+        assert_eq!(Ok(()), sh("echo needle; kill -SEGV $$", segv.clone()));
+        assert!(sh("echo needle; kill -ABRT $$", segv).is_err());
+        assert_eq!(Ok(()), sh("echo needle; kill -ABRT $$", ExitPredicate::signal("any").unwrap()));
+        assert!(sh("echo needle", ExitPredicate::signal("any").unwrap()).is_err());
+
+        let differs = |other: &str| ExitPredicate::DiffersFrom(other.split_whitespace().map(OsString::from).collect());
+        assert_eq!(Ok(()), sh("echo needle", differs("false")));
+        assert!(sh("echo needle", differs("true")).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_timeout() {
+        let timeout = |exit| Predicate {
+            exit,
+            output: Some(OutputPredicate::contains("needle")),
+            timeout: Some(Duration::from_millis(500)),
+        };
+        let start = std::time::Instant::now();
+        // The background `sleep` holds on to the output, so this only
+        // returns in time if it's killed as well.
+        let script = "sleep 30 & echo needle; sleep 30";
+        assert!(sh_with(script, timeout(ExitPredicate::Any)).is_err());
+        assert_eq!(Ok(()), sh_with(script, timeout(ExitPredicate::TimesOut)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        assert_eq!(Ok(()), sh_with("echo needle", timeout(ExitPredicate::Any)));
+        assert!(sh_with("echo needle", timeout(ExitPredicate::TimesOut)).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn copies_get_their_own_target_dir() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path().join("testy");
+        std::fs::create_dir_all(root.join("src"))?;
+        std::fs::create_dir_all(root.join("target/debug"))?;
+        std::fs::create_dir_all(root.join(".git"))?;
+        std::fs::create_dir_all(root.join("src/target"))?;
+        std::fs::write(root.join("Cargo.toml"), "")?;
+        std::fs::write(root.join("src/lib.rs"), "mod target;")?;
+        std::fs::write(root.join("src/target/mod.rs"), "")?;
+
+        let standard = |find: &str| Standard::new(vec![OsString::from("sh"), OsString::from("-c"), OsString::from("echo $CARGO_TARGET_DIR")],
+                                                  OutputPredicate::contains(find).into(),
+                                                  root.clone(),
+                                                  root.join("src/lib.rs"));
+
+        let copies = TempDir::new("reduce")?;
+        let (copy_root, copy_path) = standard("").copy_to(copies.path())?;
+        assert_eq!(copy_path, copy_root.join("src/lib.rs"));
+        assert!(copy_path.is_file());
+        assert!(copy_root.join("Cargo.toml").is_file());
+        assert!(!copy_root.join("target").exists());
+        assert!(!copy_root.join(".git").exists());
+        // only the crate's own `target` is left out
+        assert!(copy_root.join("src/target/mod.rs").is_file());
+
+        let copy_target = copy_root.join("target");
+        let runnable = standard(copy_target.to_str().unwrap());
+        assert_eq!(Ok(()), runnable.run_in(&copy_root, &copy_path));
+        assert!(runnable.run().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cached_outcomes_depend_on_the_rest_of_the_crate() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path().to_owned();
+        std::fs::create_dir_all(root.join("src"))?;
+        std::fs::write(root.join("Cargo.toml"), "[package]\nname = \"a\"\n")?;
+        std::fs::write(root.join("src/lib.rs"), "mod a;")?;
+        std::fs::write(root.join("src/a.rs"), "")?;
+        let standard = Standard::new(vec![OsString::from("true")], OutputPredicate::contains("").into(), root.clone(), root.join("src/lib.rs"));
+        let before = standard.describe();
+
+        // the file being reduced is what's cached
+        std::fs::write(root.join("src/lib.rs"), "")?;
+        assert_eq!(standard.describe(), before);
+
+        std::fs::write(root.join("src/a.rs"), "fn a() {}")?;
+        let module_changed = standard.describe();
+        assert_ne!(module_changed, before);
+
+        std::fs::write(root.join("Cargo.toml"), "[package]\nname = \"b\"\n")?;
+        assert_ne!(standard.describe(), module_changed);
+        Ok(())
+    }
+
+    #[test]
+    fn entry_points_default_targets() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path();
+        std::fs::create_dir_all(root.join("src/bin"))?;
+        std::fs::write(root.join("Cargo.toml"), "[package]\nname = \"my-crate\"\nversion = \"0.1.0\"\n")?;
+        for file in &["src/lib.rs", "src/main.rs", "src/bin/other.rs"] {
+            std::fs::write(root.join(file), "")?;
+        }
+
+        let mut entries = Vec::new();
+        entry_points(root.to_owned(), &mut entries)?;
+        let found: Vec<_> = entries.iter()
+            .map(|entry| (entry.target.as_str(), entry.path.strip_prefix(root).unwrap().to_owned()))
+            .collect();
+        assert_eq!(found, vec![
+            ("my_crate", PathBuf::from("src/lib.rs")),
+            ("my-crate", PathBuf::from("src/main.rs")),
+            ("other", PathBuf::from("src/bin/other.rs")),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn entry_points_of_workspace_members() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path();
+        std::fs::write(root.join("Cargo.toml"), "[workspace]\nmembers = [\"crates/*\", \"tool\"]\n")?;
+        for (dir, name) in &[("crates/b", "b"), ("crates/a", "a"), ("tool", "tool")] {
+            std::fs::create_dir_all(root.join(dir).join("src"))?;
+            std::fs::write(root.join(dir).join("Cargo.toml"), format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name))?;
+            std::fs::write(root.join(dir).join("src/lib.rs"), "")?;
+        }
+        // Not a crate, so not a member.
+        std::fs::create_dir_all(root.join("crates/docs"))?;
+
+        let mut entries = Vec::new();
+        entry_points(root.to_owned(), &mut entries)?;
+        let found: Vec<_> = entries.iter()
+            .map(|entry| entry.path.strip_prefix(root).unwrap().to_owned())
+            .collect();
+        assert_eq!(found, vec![
+            PathBuf::from("crates/a/src/lib.rs"),
+            PathBuf::from("crates/b/src/lib.rs"),
+            PathBuf::from("tool/src/lib.rs"),
+        ]);
+
+        std::fs::write(root.join("tool/Cargo.toml"), "[package\n")?;
+        let error = entry_points(root.to_owned(), &mut Vec::new()).unwrap_err();
+        assert!(error.contains("tool/Cargo.toml"), "{}", error);
+        Ok(())
+    }
+
     fn reduce(root: PathBuf, find: &str, cargo_arg: &str) {
+        reduce_with(root, find, cargo_arg, Options::default())
+    }
+
+    fn reduce_with(root: PathBuf, find: &str, cargo_arg: &str, options: Options) {
         let args = vec![
-            OsString::from(home().join(&OsString::from(".cargo/bin/cargo"))),
+            OsString::from(home().join(OsString::from(".cargo/bin/cargo"))),
             OsString::from(cargo_arg)];
 
-        let runnable = Standard::new(args, find.to_owned(), root);
-        assert_eq!(Ok(()), runnable.run());
-        rust_reduce::reduce(runnable);
+        let mut entries = Vec::new();
+        entry_points(root.clone(), &mut entries).unwrap();
+        for entry in entries {
+            let runnable = Standard::new(args.clone(), OutputPredicate::contains(find).into(), root.clone(), entry.path);
+            assert_eq!(Ok(()), runnable.run());
+            let options = Options {
+                output: Some(runnable.get_path().with_extension("rs.min")),
+                ..options.clone()
+            };
+            rust_reduce::reduce(runnable, &options).expect("reduction failed");
+        }
     }
 
     fn cargo<I,S>(pwd: &Path, args: I) -> std::io::Result<std::process::ExitStatus>
         where I: IntoIterator<Item=S>, S: AsRef<OsStr>
     {
-        let cargo_path = home().join(&OsString::from(".cargo/bin/cargo"));
+        let cargo_path = home().join(OsString::from(".cargo/bin/cargo"));
         let mut cmd = std::process::Command::new(&cargo_path);
         let cmd = cmd.args(args);
         cmd.current_dir(pwd);
@@ -236,7 +691,7 @@ mod tests {
         let root = loc.path().join("testy");
         let main_file = root.join("src/main.rs");
 
-        cargo(loc.path(), &vec!["new", "testy"])?;
+        cargo(loc.path(), vec!["new", "testy"])?;
 
         std::fs::write(&main_file, r#"
 fn unused() {}
@@ -253,31 +708,53 @@ pub fn main() {
         Ok(())
     }
 
+    #[test]
+    fn formats_with_the_crates_edition() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path().join("testy");
+        let p = root.join("src/lib.rs");
+
+        cargo(loc.path(), vec!["new", "testy", "--lib", "--edition", "2015"])?;
+        // `dyn` is a keyword from 2018 on.
+        std::fs::write(&p, r#"
+fn dyn() {}
+
+#[test]
+fn it_works() { dyn() }
+"#)?;
+
+        reduce(root, "test result: ok. 1 passed", "test");
+
+        let min = read_file(&p.with_extension("rs.min"));
+        assert!(min.contains("fn dyn() {"), "{}", min);
+        Ok(())
+    }
+
     #[test]
     fn hello_lib_works() -> Test {
         let loc = TempDir::new("reduce")?;
         let root = loc.path().join("testy");
         let p = root.join("src/lib.rs");
 
-        cargo(loc.path(), &vec!["new", "testy", "--lib"])?;
+        cargo(loc.path(), vec!["new", "testy", "--lib"])?;
 
         reduce(root, "test result: ok","test");
 
-        assert_eq!(std::fs::read_to_string(&p.with_extension("rs.min"))?.trim(), r#""#);
+        assert_eq!(std::fs::read_to_string(p.with_extension("rs.min"))?.trim(), r#""#);
         Ok(())
     }
 
     #[test]
     fn hello_lib_works_min_to_1_test() -> Test {
         let loc = TempDir::new("reduce")?;
-        cargo(loc.path(), &vec!["new", "testy", "--lib"])?;
+        cargo(loc.path(), vec!["new", "testy", "--lib"])?;
 
         let root = loc.path().join("testy");
         let p = root.join("src/lib.rs");
 
         reduce(root, "test result: ok. 1 passed","test");
         let minimised = std::fs::read_to_string(
-            &p.with_extension("rs.min"))?;
+            p.with_extension("rs.min"))?;
 
         assert_eq!(minimised, r#"#[cfg(test)]
 mod tests {
@@ -288,6 +765,282 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn splits_modules_back_into_their_files() -> Test {
+        let loc = TempDir::new("reduce")?;
+        cargo(loc.path(), vec!["new", "testy"])?;
+
+        let root = loc.path().join("testy");
+        let p = root.join("src/main.rs");
+        std::fs::create_dir_all(root.join("src/a"))?;
+        std::fs::write(&p, "mod a;\nfn unused() {}\nfn main() { println!(\"answer {}\", a::b::f()) }\n")?;
+        std::fs::write(root.join("src/a.rs"), "pub mod b;\npub fn unused() {}\n")?;
+        std::fs::write(root.join("src/a/b.rs"), "pub fn f() -> u32 { 1 }\npub fn unused() {}\n")?;
+
+        reduce_with(root, "answer 1", "run", Options { split_modules: true, ..Options::default() });
+
+        let out = p.with_extension("rs.min");
+        assert_eq!(read_file(&out.join("main.rs")), "mod a;\nfn main() {\n    println!(\"answer {}\", a::b::f())\n}\n");
+        assert_eq!(read_file(&out.join("a.rs")), "pub mod b;\n");
+        assert_eq!(read_file(&out.join("a/b.rs")), "pub fn f() -> u32 {\n    1\n}\n");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn splits_modules_by_their_path_attribute() -> Test {
+        let loc = TempDir::new("reduce")?;
+        cargo(loc.path(), vec!["new", "testy"])?;
+
+        let root = loc.path().join("testy");
+        let p = root.join("src/main.rs");
+        std::fs::write(&p, r#"
+#[cfg(not(unix))]
+#[path = "other.rs"]
+mod imp;
+
+#[cfg(unix)]
+#[path = "unix.rs"]
+mod imp;
+
+fn main() { println!("answer {}", imp::f()) }
+"#)?;
+        std::fs::write(root.join("src/other.rs"), "pub fn f() -> u32 { 2 }\n")?;
+        std::fs::write(root.join("src/unix.rs"), "pub fn f() -> u32 { 1 }\npub fn unused() {}\n")?;
+
+        reduce_with(root, "answer 1", "run", Options { split_modules: true, ..Options::default() });
+
+        let out = p.with_extension("rs.min");
+        let main = read_file(&out.join("main.rs"));
+        assert!(main.contains("#[path = \"unix.rs\"]\nmod imp;\n"), "{}", main);
+        assert_eq!(read_file(&out.join("unix.rs")), "pub fn f() -> u32 {\n    1\n}\n");
+        if let Ok(other) = std::fs::read_to_string(out.join("other.rs")) {
+            assert!(!other.contains("{ 1 }"), "{}", other);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cached_outcomes_are_not_rerun() -> Test {
+        let loc = TempDir::new("reduce")?;
+        cargo(loc.path(), vec!["new", "testy", "--lib"])?;
+
+        let root = loc.path().join("testy");
+        let cache = loc.path().join("cache");
+        let options = || Options { cache: Some(cache.clone()), ..Options::default() };
+
+        reduce_with(root.clone(), "test result: ok. 1 passed", "test", options());
+        let first = std::fs::read_to_string(&cache)?;
+        assert!(!first.is_empty());
+
+        // Everything the second run tries was already tried by the first.
+        reduce_with(root, "test result: ok. 1 passed", "test", options());
+        assert_eq!(std::fs::read_to_string(&cache)?, first);
+        Ok(())
+    }
+
+    #[test]
+    fn resumes_from_checkpoint() -> Test {
+        let loc = TempDir::new("reduce")?;
+        cargo(loc.path(), vec!["new", "testy", "--lib"])?;
+
+        let root = loc.path().join("testy");
+        let p = root.join("src/lib.rs");
+        let checkpoint = loc.path().join("checkpoint.toml");
+        // Killed during the last pass of a round that didn't make progress,
+        // so `unused` is only left in if we really carry on from there.
+        std::fs::write(&checkpoint, format!(r#"
+path = {:?}
+round = 1
+before = 1
+pass = "privatise"
+cursor = [0]
+source = "fn unused () {{ }} # [cfg (test)] mod tests {{ # [test] fn it_works () {{ }} }}"
+"#, p.to_str().unwrap()))?;
+
+        reduce_with(root, "test result: ok. 1 passed", "test", Options {
+            checkpoint: Some(checkpoint.clone()),
+            resume: true,
+            ..Options::default()
+        });
+
+        assert!(std::fs::read_to_string(p.with_extension("rs.min"))?.contains("fn unused() {}"));
+        assert!(!checkpoint.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn reducer_runs_given_passes() -> Test {
+        let loc = TempDir::new("reduce")?;
+        cargo(loc.path(), vec!["new", "testy", "--lib"])?;
+
+        let root = loc.path().join("testy");
+        let p = root.join("src/lib.rs");
+        let args = vec![home().join(".cargo/bin/cargo").into_os_string(), OsString::from("build")];
+        let runnable = Standard::new(args, OutputPredicate::contains("Finished").into(), root, p.clone());
+        let everything = rust_reduce::FnPass::new("everything", |file: &mut syn::File, try_compile: &mut dyn FnMut(&syn::File) -> Result<(), String>| {
+            let mut candidate = file.clone();
+            candidate.items.clear();
+            if try_compile(&candidate).is_ok() {
+                *file = candidate;
+            }
+        });
+
+        let reduced = rust_reduce::Reducer::new(runnable)
+            .options(Options { output: Some(p.with_extension("rs.min")), ..Options::default() })
+            .passes(vec![Box::new(everything)])
+            .reduce()?;
+        assert!(reduced.items.is_empty());
+        Ok(())
+    }
+
+    /// Interesting as long as the file still has `fn keep`.
+    struct Keep {
+        root: PathBuf,
+        path: PathBuf,
+    }
+
+    impl Runnable for Keep {
+        fn root(&self) -> &Path {
+            &self.root
+        }
+
+        fn get_path(&self) -> &Path {
+            &self.path
+        }
+
+        fn run_in(&self, _root: &Path, path: &Path) -> Result<(), String> {
+            match read_file(path).contains("fn keep") {
+                true => Ok(()),
+                false => Err("no fn keep".to_owned()),
+            }
+        }
+    }
+
+    /// Hands the same batch to the workers each time it's run.
+    struct Batch(std::sync::Arc<std::sync::Mutex<Vec<Option<usize>>>>);
+
+    impl rust_reduce::Pass for Batch {
+        fn name(&self) -> &str {
+            "batch"
+        }
+
+        fn run(&mut self, file: &mut syn::File, tester: &mut rust_reduce::Tester) {
+            let candidates: Vec<syn::File> = ["fn drop() {}", "fn keep() { let long = 1; }", "fn keep() {}"].iter()
+                .map(|source| syn::parse_str(source).unwrap())
+                .collect();
+            let winner = tester.test_batch(&candidates);
+            self.0.lock().unwrap().push(winner);
+            if let Some(winner) = winner {
+                *file = candidates[winner].clone();
+            }
+        }
+    }
+
+    #[test]
+    fn workers_pick_the_first_interesting_candidate() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path().to_owned();
+        let path = root.join("lib.rs");
+        std::fs::write(&path, "fn keep() { let long = 1; } fn other() {}")?;
+
+        let winners = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        rust_reduce::Reducer::new(Keep { root, path: path.clone() })
+            .options(Options { jobs: 3, output: Some(path.with_extension("rs.min")), ..Options::default() })
+            .passes(vec![Box::new(Batch(winners.clone()))])
+            .reduce()?;
+        // the same as one at a time, even though the last one is smaller
+        assert_eq!(winners.lock().unwrap()[0], Some(1));
+        Ok(())
+    }
+
+    /// Records where it was told to start, but never changes anything.
+    struct Start(&'static str, std::sync::Arc<std::sync::Mutex<Vec<[usize; 1]>>>);
+
+    impl rust_reduce::Pass for Start {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn run(&mut self, _file: &mut syn::File, tester: &mut rust_reduce::Tester) {
+            if self.0 != "early" {
+                self.1.lock().unwrap().push(tester.cursor().start([0]));
+            }
+        }
+    }
+
+    #[test]
+    fn unused_resume_position_is_dropped() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path().to_owned();
+        let path = root.join("lib.rs");
+        std::fs::write(&path, "fn keep() {}")?;
+        let checkpoint = root.join("checkpoint.toml");
+        // `early` returns before asking where to start.
+        std::fs::write(&checkpoint, format!(r#"
+path = {:?}
+round = 1
+before = 1
+pass = "early"
+cursor = [5]
+source = "fn keep () {{ }}"
+"#, path.to_str().unwrap()))?;
+
+        let starts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        rust_reduce::Reducer::new(Keep { root, path: path.clone() })
+            .options(Options {
+                checkpoint: Some(checkpoint),
+                resume: true,
+                output: Some(path.with_extension("rs.min")),
+                ..Options::default()
+            })
+            .passes(vec![Box::new(Start("early", starts.clone())), Box::new(Start("late", starts.clone()))])
+            .reduce()?;
+        assert_eq!(starts.lock().unwrap()[0], [0]);
+        Ok(())
+    }
+
+    #[test]
+    fn file_outside_root_is_an_error() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path().join("root");
+        std::fs::create_dir(&root)?;
+        let path = loc.path().join("lib.rs");
+        std::fs::write(&path, "fn keep() {}")?;
+
+        let result = rust_reduce::Reducer::new(Keep { root, path: path.clone() })
+            .options(Options { jobs: 2, output: Some(path.with_extension("rs.min")), ..Options::default() })
+            .reduce();
+        assert!(matches!(result, Err(rust_reduce::ReduceError::Io(..))), "{:?}", result);
+        assert_eq!(read_file(&path), "fn keep() {}");
+        Ok(())
+    }
+
+    #[test]
+    fn select_passes() {
+        let names = |only: &[&str], skip: &[&str]| rust_reduce::transforms::select_passes(only, skip, &Options::default())
+            .map(|passes| passes.iter().map(|pass| pass.name().to_owned()).collect::<Vec<_>>());
+
+        assert_eq!(names(&[], &["remove-doc", "privatise"]).unwrap(),
+                   vec!["eval-cfg", "prune", "remove-derive", "remove-derive-traits", "remove-attrs", "empty-blocks", "clear-blocks", "remove-default-bodies", "remove-stmts", "simplify-exprs", "reduce-signatures", "reduce-generics"]);
+        assert_eq!(names(&["clear-blocks", "prune"], &[]).unwrap(), vec!["clear-blocks", "prune"]);
+        assert_eq!(names(&["clear-blocks", "prune"], &["prune"]).unwrap(), vec!["clear-blocks"]);
+        assert!(names(&["prune", "prune"], &[]).is_err());
+        assert!(names(&[], &["unknown"]).is_err());
+    }
+
+    #[test]
+    fn reducer_returns_errors() {
+        let r = Standard::new(vec![OsString::from("echo"), OsString::from("haystack")],
+                              OutputPredicate::contains("needle").into(),
+                              std::env::current_dir().unwrap(),
+                              PathBuf::from("src/lib.rs"));
+        match rust_reduce::Reducer::new(r).reduce() {
+            Err(rust_reduce::ReduceError::NotInteresting(_)) => {},
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
     /// We need to make sure we inline things like this otherwise code doesn't compile:
     /// #[cfg(test)]
     /// mod test_utils;
@@ -296,9 +1049,9 @@ mod tests {
         let loc = TempDir::new("reduce")?;
         let root = loc.path().join("testy");
 
-        cargo(loc.path(), &vec!["new", "testy", "--lib"])?;
+        cargo(loc.path(), vec!["new", "testy", "--lib"])?;
 
-        std::fs::write(& root.join("src/lib.rs"), r#"
+        std::fs::write(root.join("src/lib.rs"), r#"
 #[cfg(test)]
 mod test_utils;
 
@@ -312,7 +1065,7 @@ mod tests {
     }
 }
         "#)?;
-        std::fs::write(& root.join("src/test_utils.rs"), r#"
+        std::fs::write(root.join("src/test_utils.rs"), r#"
 pub fn util() -> &'static str {
     "wonderful"
 }
@@ -320,12 +1073,14 @@ pub fn util() -> &'static str {
 
         let p = root.join("src/lib.rs");
 
-        reduce(root, "test result: ok. 1 passed","test");
+        // A second round would get rid of `test_utils` altogether.
+        let options = Options { max_rounds: Some(1), ..Options::default() };
+        reduce_with(root, "test result: ok. 1 passed", "test", options);
         assert_eq!(std::fs::read_to_string(
-            &p.with_extension("rs.min"))?, r#"#[cfg(test)]
+            p.with_extension("rs.min"))?, r#"#[cfg(test)]
 mod test_utils {
-    pub fn util() -> &'static str {
-        unimplemented!()
+    fn util() {
+        unimplemented!();
     }
 }
 #[cfg(test)]
@@ -337,4 +1092,50 @@ mod tests {
 "#);
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn manifests_drop_unused_members_dependencies_features_and_profiles() -> Test {
+        let loc = TempDir::new("reduce")?;
+        let root = loc.path().to_owned();
+        std::fs::write(root.join("Cargo.toml"), "# The workspace.\n[workspace]\nmembers = [\"a\", \"b\"] # trailing\n\n[profile.dev]\nopt-level = 1\n")?;
+        cargo(&root, vec!["new", "a", "--lib"])?;
+        cargo(&root, vec!["new", "b", "--lib"])?;
+        let mut a_toml = read_file(&root.join("a/Cargo.toml"));
+        a_toml.push_str("b = { path = \"../b\", features = [\"x\"] }\n\
+                         [dev-dependencies]\nb = { path = \"../b\" }\n\
+                         [features]\nextra = []\n");
+        std::fs::write(root.join("a/Cargo.toml"), a_toml)?;
+        let mut b_toml = read_file(&root.join("b/Cargo.toml"));
+        b_toml.push_str("[features]\nx = []\n");
+        std::fs::write(root.join("b/Cargo.toml"), b_toml)?;
+        std::fs::write(root.join("a/src/lib.rs"), "pub fn f() { let x = 1; }\n")?;
+
+        let args = vec![
+            OsString::from(home().join(OsString::from(".cargo/bin/cargo"))),
+            OsString::from("check")];
+        let runnable = Standard::new(args, OutputPredicate::contains("unused variable").into(), root.clone(), root.join("Cargo.toml"));
+        assert_eq!(Ok(()), runnable.run());
+
+        let mut manifests = Manifests::load(&root)?;
+        manifests.reduce_members(&runnable)?;
+        manifests.reduce_dependencies(&runnable)?;
+        manifests.reduce_features(&runnable)?;
+        manifests.reduce_profiles(&runnable)?;
+        manifests.write_min()?;
+
+        // Only what was removed changes, comments and all.
+        assert_eq!(read_file(&root.join("Cargo.toml.min")), "# The workspace.\n[workspace]\nmembers = [\"a\"] # trailing\n");
+        let workspace: toml::Value = toml::from_str(&read_file(&root.join("Cargo.toml.min")))?;
+        assert_eq!(workspace["workspace"]["members"], toml::Value::Array(vec!["a".into()]));
+        assert!(workspace.get("profile").is_none());
+        let a: toml::Value = toml::from_str(&read_file(&root.join("a/Cargo.toml.min")))?;
+        for section in &["dependencies", "dev-dependencies", "features"] {
+            assert_eq!(a.get(section).and_then(toml::Value::as_table).map(|t| t.len()).unwrap_or(0), 0,
+                       "{} left in {}", section, a);
+        }
+        // The originals are put back.
+        assert!(read_file(&root.join("Cargo.toml")).contains("opt-level"));
+        assert!(read_file(&root.join("a/Cargo.toml")).contains("extra"));
+        Ok(())
+    }
+}
@@ -1,14 +1,41 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ffi::OsString;
+use std::time::Duration;
 
 use clap::clap_app;
-use rust_reduce::TestScript;
+use rust_reduce::{handle_interrupts, Options, Reducer, TestScript};
+use rust_reduce::transforms::eval_cfg::CfgSet;
+use rust_reduce::predicate::{ExitPredicate, OutputPredicate, Predicate, Stream};
+use regex::Regex;
 
 fn main() {
     let matches = clap_app!(("rust-reduce") =>
         (version: clap::crate_version!())
-        (@arg CMD: * "Command to run.")
-        (@arg ARGS: * ... "Arguments to the command to run.
+        (@arg DDMIN: --ddmin "Remove items in ever smaller chunks before trying them one by one. Much faster on large inputs with lots of dead code.")
+        (@arg SPLIT_MODULES: --("split-modules") "Split the result back into the files its modules were originally loaded from, deleting the ones that are no longer needed. With --output, the files are written into that directory instead.")
+        (@arg OUTPUT: -o --output +takes_value "Write the reduced file here and restore the original, instead of overwriting the original.")
+        (@arg CACHE: --cache +takes_value "Record whether each candidate was interesting in this file, and skip candidates already recorded there, e.g. by an earlier run that was interrupted.")
+        (@arg CHECKPOINT: --checkpoint +takes_value "Every now and then, save the smallest interesting version so far and how far each pass has got to this file. It's removed once the reduction is done.")
+        (@arg RESUME: --resume requires[CHECKPOINT] "Carry on from the --checkpoint file, if there is one, instead of starting over.")
+        (@arg PASSES: --passes +takes_value "Only run these passes, in this order, separated by commas. See --list-passes.")
+        (@arg SKIP: --skip +takes_value "Don't run these passes, separated by commas, e.g. `remove-doc` when reducing a rustdoc bug.")
+        (@arg KEEP_ATTRS: --("keep-attrs") +takes_value "Never remove attributes with these paths, separated by commas, e.g. `repr,inline`.")
+        (@arg ONLY_ATTRS: --("only-attrs") +takes_value "Only try removing attributes with these paths, separated by commas, e.g. `cfg_attr,allow`. By default, any attribute but `cfg` is tried.")
+        (@arg EVAL_CFG: --("eval-cfg") "Remove code that's configured out and expand #[cfg_attr]s that apply, using the cfgs rustc sets for the host, or for --cfg-target, along with --cfg and --features. Implied by those.")
+        (@arg CFG: --cfg +takes_value +multiple number_of_values(1) "Set a cfg for --eval-cfg, like `rustc --cfg`, e.g. `test` or `feature=\"std\"`. May be given more than once.")
+        (@arg FEATURES: --features +takes_value "Enable these features for --eval-cfg, separated by commas.")
+        (@arg CFG_TARGET: --("cfg-target") +takes_value "Use the cfgs rustc sets for this target triple for --eval-cfg, instead of those for the host.")
+        (@arg LIST_PASSES: --("list-passes") "List the passes, in the order they're run by default, and exit.")
+        (@arg MAX_ROUNDS: --("max-rounds") +takes_value "Stop after this many rounds of all passes. By default, rounds are repeated until one doesn't make the file any smaller.")
+        (@arg JOBS: -j --jobs +takes_value "Number of candidates to test concurrently, each in its own temporary directory. Only prune, remove-attrs, remove-stmts, simplify-exprs, reduce-signatures and reduce-generics test more than one at a time.")
+        (@arg EXIT_CODE: --("exit-code") +takes_value conflicts_with[SIGNAL DIFFERS_FROM HANG] "The command is interesting if it exits with this code, instead of 0.")
+        (@arg SIGNAL: --signal +takes_value conflicts_with[DIFFERS_FROM HANG] "The command is interesting if it's killed by this signal, e.g. SIGSEGV or SIGABRT, or by any signal with `any`. If the command runs cargo, it exits with code 101 when rustc crashes, so look for e.g. `(signal: 11, SIGSEGV` in its output instead.")
+        (@arg DIFFERS_FROM: --("differs-from") +takes_value conflicts_with[HANG] "The command is interesting if it exits differently from this other command, split on whitespace and given the path to the candidate the same way, e.g. to compare debug and release builds.")
+        (@arg TIMEOUT: --timeout +takes_value "Kill the command, and anything it started, after this many seconds. Unless --hang is given, that makes the candidate uninteresting.")
+        (@arg HANG: --hang requires[TIMEOUT] "The command is interesting if it times out, e.g. to reduce a compiler hang.")
+        (@arg FIND: --find +takes_value +multiple number_of_values(1) "Text that must also be found in the command's output. May be given more than once.")
+        (@arg CMD: required_unless[LIST_PASSES] "Command to run.")
+        (@arg ARGS: ... required_unless[LIST_PASSES] "Arguments to the command to run.
 
 The last argument must be the path of the existing file of interest. CMD will be invoked with the last argument replaced with the path to a temporary file.
 
@@ -16,17 +43,97 @@ You can use `--` to separate ARGS from any arguments passed to `rust-reduce`.")
         (after_help: "\
 `rust-reduce` will try to make the source file smaller by interpreting it as valid Rust code and intelligently removing parts of the code. After each removal, the given command will be run but passing a path to a file containing the reduced code. The command should return 0 if run on the original input, and also if the reduced code is interesting, non-0 otherwise.
 
-The original file will be overwritten with the smallest interesting reduced version, if found. This happens while `rust-reduce` is running. The original file will be backed up with the `.orig` suffix. If `rustfmt` is found, it will be used to clean up the output.
+The original file will be overwritten with the smallest interesting reduced version, if found. This happens once `rust-reduce` is done; until then, candidates are only ever written to a temporary directory. The original file will be backed up with the `.orig` suffix, and restored if `rust-reduce` is interrupted with Ctrl-C. With `--output`, the original file is restored at the end and the reduced version is written to the given path instead. If `rustfmt` is found, it will be used to clean up the output.
 
 A common way to use `rust-reduce` is to write a short shell script that runs `rustc` and greps the compiler output for a particular error message. NB. you will want to look for a specific error message because while `rust-reduce` will generate syntactically correct code, it's not guaranteed to compile.
 
 The original file may refer to modules in different files, these will be inlined and reduced along with the main file.")
     ).get_matches();
 
+    let list = |arg| matches.value_of(arg).map_or(Vec::new(), |list: &str| list.split(',').map(str::trim).collect::<Vec<_>>());
+    let options = Options {
+        ddmin: matches.is_present("DDMIN"),
+        jobs: matches.value_of("JOBS")
+            .map(|jobs| jobs.parse().expect("--jobs takes a number"))
+            .unwrap_or(1),
+        max_rounds: matches.value_of("MAX_ROUNDS")
+            .map(|rounds| rounds.parse().expect("--max-rounds takes a number")),
+        output: matches.value_of_os("OUTPUT").map(PathBuf::from),
+        split_modules: matches.is_present("SPLIT_MODULES"),
+        cache: matches.value_of_os("CACHE").map(PathBuf::from),
+        checkpoint: matches.value_of_os("CHECKPOINT").map(PathBuf::from),
+        resume: matches.is_present("RESUME"),
+        keep_attrs: list("KEEP_ATTRS").into_iter().map(String::from).collect(),
+        only_attrs: list("ONLY_ATTRS").into_iter().map(String::from).collect(),
+        cfg: cfg_set(&matches),
+    };
+    if matches.is_present("LIST_PASSES") {
+        for pass in rust_reduce::transforms::default_passes(&options) {
+            println!("{:<22}{}", pass.name(), pass.description());
+        }
+        return;
+    }
+    let (only, skip) = (list("PASSES"), list("SKIP"));
+    let passes = || rust_reduce::transforms::select_passes(&only, &skip, &options).unwrap_or_else(|e| {
+        eprintln!("rust-reduce: {}", e);
+        std::process::exit(1);
+    });
+    // Check them before doing anything else.
+    passes();
+
     let mut cmd = vec![matches.value_of_os("CMD").expect("validated").to_owned()];
     let mut iter = matches.values_of_os("ARGS").expect("validated").map(ToOwned::to_owned);
     let file: OsString = iter.next_back().expect("validated");
     cmd.extend(iter);
-    let action = TestScript{ cmd, path: Path::new(&file) };
-    rust_reduce::reduce( action);
+    let exit = if let Some(code) = matches.value_of("EXIT_CODE") {
+        ExitPredicate::Code(code.parse().expect("--exit-code takes a number"))
+    } else if let Some(signal) = matches.value_of("SIGNAL") {
+        ExitPredicate::signal(signal).unwrap_or_else(|e| {
+            eprintln!("rust-reduce: {}", e);
+            std::process::exit(1);
+        })
+    } else if let Some(other) = matches.value_of("DIFFERS_FROM") {
+        ExitPredicate::DiffersFrom(other.split_whitespace().map(OsString::from).collect())
+    } else if matches.is_present("HANG") {
+        ExitPredicate::TimesOut
+    } else {
+        ExitPredicate::Success
+    };
+    let output = matches.values_of("FIND").map(|find| OutputPredicate {
+        patterns: find.map(|text| Regex::new(&regex::escape(text)).expect("escaped")).collect(),
+        forbidden: Vec::new(),
+        any: false,
+        stream: Stream::Both,
+    });
+    let timeout = matches.value_of("TIMEOUT")
+        .map(|secs| Duration::from_secs_f64(secs.parse().expect("--timeout takes a number of seconds")));
+    let predicate = Predicate { exit, output, timeout };
+    let action = TestScript::new(cmd, predicate, Path::new(&file))
+        .unwrap_or_else(|e| {
+            eprintln!("rust-reduce: {}", e);
+            std::process::exit(1);
+        });
+    handle_interrupts();
+    let reducer = Reducer::new(action)
+        .passes(passes())
+        .options(options)
+        .progress(|msg| println!("{}", msg));
+    if let Err(e) = reducer.reduce() {
+        eprintln!("rust-reduce: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// The configuration for the `eval-cfg` pass, if it's to do anything.
+fn cfg_set(matches: &clap::ArgMatches) -> Option<CfgSet> {
+    if !["EVAL_CFG", "CFG", "FEATURES", "CFG_TARGET"].iter().any(|arg| matches.is_present(arg)) {
+        return None;
+    }
+    let cfgs = matches.values_of("CFG").into_iter().flatten();
+    let cfg = CfgSet::from_args(matches.value_of("CFG_TARGET"), cfgs, matches.value_of("FEATURES"))
+        .unwrap_or_else(|e| {
+            eprintln!("rust-reduce: {}", e);
+            std::process::exit(1);
+        });
+    Some(cfg)
 }
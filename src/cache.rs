@@ -0,0 +1,126 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+//! Remember which candidates were interesting, so the same one is never run
+//! twice.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+type Key = [u8; 32];
+
+pub(crate) struct Cache {
+    /// Mixed into every key, so outcomes of a different test don't count.
+    salt: Vec<u8>,
+    outcomes: RefCell<HashMap<Key, bool>>,
+    /// Where new outcomes are appended to, if they're kept across runs.
+    file: RefCell<Option<File>>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+}
+
+impl Cache {
+    /// An empty cache, or one loaded from `path` which new outcomes are then
+    /// added to.
+    pub(crate) fn new(salt: &str, path: Option<&Path>) -> std::io::Result<Cache> {
+        let mut outcomes = HashMap::new();
+        let file = match path {
+            Some(path) => {
+                if path.exists() {
+                    for line in BufReader::new(File::open(path)?).lines() {
+                        if let Some((key, interesting)) = parse_line(&line?) {
+                            outcomes.insert(key, interesting);
+                        }
+                    }
+                }
+                Some(OpenOptions::new().create(true).append(true).open(path)?)
+            },
+            None => None,
+        };
+        Ok(Cache {
+            salt: salt.as_bytes().to_owned(),
+            outcomes: RefCell::new(outcomes),
+            file: RefCell::new(file),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        })
+    }
+
+    pub(crate) fn key(&self, source: &str) -> Key {
+        let mut hasher = Sha256::new();
+        hasher.update((self.salt.len() as u64).to_le_bytes());
+        hasher.update(&self.salt);
+        hasher.update(source.as_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Whether the candidate with this key was interesting, if it's been
+    /// tried before.
+    pub(crate) fn get(&self, key: &Key) -> Option<bool> {
+        let outcome = self.outcomes.borrow().get(key).copied();
+        match outcome {
+            Some(_) => self.hits.set(self.hits.get() + 1),
+            None => self.misses.set(self.misses.get() + 1),
+        }
+        outcome
+    }
+
+    pub(crate) fn insert(&self, key: Key, interesting: bool) -> std::io::Result<()> {
+        self.outcomes.borrow_mut().insert(key, interesting);
+        if let Some(file) = &mut *self.file.borrow_mut() {
+            writeln!(file, "{} {}", hex(&key), interesting as u8)?;
+        }
+        Ok(())
+    }
+
+    /// How many outcomes are known.
+    pub(crate) fn len(&self) -> usize {
+        self.outcomes.borrow().len()
+    }
+
+    pub(crate) fn report(&self) -> String {
+        let (hits, misses) = (self.hits.get(), self.misses.get());
+        format!("Cache: {} hits, {} misses ({:.0}% hit rate)",
+                hits, misses, 100.0 * hits as f64 / (hits + misses).max(1) as f64)
+    }
+}
+
+fn hex(key: &Key) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A `<hex key> <0 or 1>` line. Anything else, e.g. a line cut short by an
+/// earlier run being killed, is ignored.
+fn parse_line(line: &str) -> Option<(Key, bool)> {
+    let (hex, interesting) = line.split_once(' ')?;
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    match interesting {
+        "0" => Some((key, false)),
+        "1" => Some((key, true)),
+        _ => None,
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+//! Save how far a reduction has got every now and then, so it can be resumed
+//! if it's killed.
+
+use std::cell::{Cell, RefCell};
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::ReduceError;
+
+/// How often to write a checkpoint while a pass is running.
+const INTERVAL: Duration = Duration::from_secs(10);
+
+/// What's written to the checkpoint file.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Checkpoint {
+    /// The file being reduced.
+    pub(crate) path: PathBuf,
+    pub(crate) round: usize,
+    /// The size the round started at, to tell whether it made progress.
+    pub(crate) before: usize,
+    pub(crate) pass: String,
+    /// See `Cursor`.
+    pub(crate) cursor: Vec<usize>,
+    /// The smallest interesting candidate so far.
+    pub(crate) source: String,
+}
+
+impl Checkpoint {
+    pub(crate) fn load(path: &Path) -> std::io::Result<Checkpoint> {
+        toml::from_str(&std::fs::read_to_string(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Where a pass has got to, e.g. `container`, `chunk` and `index` for
+/// `prune_items`. Each pass decides what the numbers mean.
+#[derive(Debug, Default)]
+pub struct Cursor {
+    /// From the checkpoint being resumed, until the pass picks it up.
+    resumed: RefCell<Vec<usize>>,
+    current: RefCell<Vec<usize>>,
+}
+
+impl Cursor {
+    /// The position to start at: the one a checkpoint was resumed from, if
+    /// any, otherwise `fresh`.
+    pub fn start<const N: usize>(&self, fresh: [usize; N]) -> [usize; N] {
+        let resumed = std::mem::take(&mut *self.resumed.borrow_mut());
+        resumed.try_into().unwrap_or(fresh)
+    }
+
+    /// Record the position of the candidate that's about to be tried.
+    pub fn set(&self, position: &[usize]) {
+        *self.current.borrow_mut() = position.to_owned();
+    }
+
+    fn position(&self) -> Vec<usize> {
+        let resumed = self.resumed.borrow();
+        if resumed.is_empty() { self.current.borrow().clone() } else { resumed.clone() }
+    }
+}
+
+/// Keeps track of the current round, pass and best candidate, and writes
+/// them out now and then.
+pub(crate) struct Checkpoints {
+    /// Where checkpoints go, if anywhere.
+    file: Option<PathBuf>,
+    path: PathBuf,
+    round: Cell<usize>,
+    before: Cell<usize>,
+    pass: RefCell<String>,
+    /// The pass the resumed cursor belongs to, until that pass starts.
+    resumed_pass: RefCell<Option<String>>,
+    pub(crate) cursor: Cursor,
+    best: RefCell<String>,
+    last_saved: Cell<Instant>,
+}
+
+impl Checkpoints {
+    pub(crate) fn new(file: Option<PathBuf>, path: &Path, source: String) -> Checkpoints {
+        Checkpoints {
+            file,
+            path: path.to_owned(),
+            round: Cell::new(1),
+            before: Cell::new(0),
+            pass: RefCell::new(String::new()),
+            resumed_pass: RefCell::new(None),
+            cursor: Cursor::default(),
+            best: RefCell::new(source),
+            last_saved: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Carry on from `checkpoint`.
+    pub(crate) fn resume(&self, checkpoint: Checkpoint) {
+        self.round.set(checkpoint.round);
+        self.before.set(checkpoint.before);
+        *self.cursor.resumed.borrow_mut() = checkpoint.cursor;
+        *self.resumed_pass.borrow_mut() = Some(checkpoint.pass);
+        *self.best.borrow_mut() = checkpoint.source;
+    }
+
+    /// Returns the size the round started at, which is `size` unless the
+    /// round was resumed halfway through.
+    pub(crate) fn start_round(&self, round: usize, size: usize) -> usize {
+        if self.round.get() != round || self.before.get() == 0 {
+            self.round.set(round);
+            self.before.set(size);
+        }
+        self.before.get()
+    }
+
+    /// A resumed position that isn't for `pass`, or that the pass it was for
+    /// never picked up, is dropped rather than handed to the wrong pass.
+    pub(crate) fn start_pass(&self, pass: &str) -> Result<(), ReduceError> {
+        if self.resumed_pass.borrow_mut().take().as_deref() != Some(pass) {
+            self.cursor.resumed.borrow_mut().clear();
+        }
+        pass.clone_into(&mut self.pass.borrow_mut());
+        self.cursor.set(&[]);
+        self.save()
+    }
+
+    /// `source` was interesting and is now the one to resume from.
+    pub(crate) fn interesting(&self, source: &str) {
+        source.clone_into(&mut self.best.borrow_mut());
+    }
+
+    /// Save a checkpoint if it's been a while.
+    pub(crate) fn tick(&self) -> Result<(), ReduceError> {
+        if self.last_saved.get().elapsed() >= INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), ReduceError> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let checkpoint = Checkpoint {
+            path: self.path.clone(),
+            round: self.round.get(),
+            before: self.before.get(),
+            pass: self.pass.borrow().clone(),
+            cursor: self.cursor.position(),
+            source: self.best.borrow().clone(),
+        };
+        let text = toml::to_string(&checkpoint).expect("checkpoint can be serialized");
+        // Never leave a half-written checkpoint behind.
+        let tmp = file.with_extension("tmp");
+        std::fs::write(&tmp, text)
+            .and_then(|()| std::fs::rename(&tmp, file))
+            .map_err(|e| ReduceError::Io(format!("Couldn't write checkpoint {}", file.display()), e))?;
+        self.last_saved.set(Instant::now());
+        Ok(())
+    }
+
+    /// The reduction is done, so there's nothing to resume any more.
+    pub(crate) fn finish(&self) {
+        if let Some(file) = &self.file {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+}
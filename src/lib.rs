@@ -15,120 +15,575 @@
 // You should have received a copy of the GNU General Public License
 // along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{ffi::OsString, io::Write, process::{Command, Stdio}};
+use std::{cell::RefCell, ffi::OsString, process::Command};
 
 use quote::ToTokens;
 use syn_inline_mod::{Error as InlineError, InlinerBuilder};
-use std::path::Path;
-
-mod transforms;
-
-pub fn reduce<R: Runnable>(runnable: R)
-{
-    if let Err(msg) = runnable.run() {
-        eprintln!("rust-reduce: run with initial input did not indicate success: {}", msg);
-        std::process::exit(1);
-    }
-
-    //println!("Reducing {:?}", runnable.get_path());
-    //println!("Reducing {:?}", runnable.get_path().canonicalize().unwrap());
-    std::io::stdout().flush().unwrap();
-    let original = std::fs::read(runnable.get_path()).unwrap();
-
-    let mut inlined_file = match InlinerBuilder::new()
-        .error_not_found(true)
-        .parse_and_inline_modules(runnable.get_path()) {
-        Ok(f) => f,
-        Err(InlineError::NotFound(missing)) => {
-            eprintln!("rust-reduce: file not found");
-            for (modname, loc) in missing {
-                eprintln!("    mod {} @ {}:{}", modname, loc.path.display(), loc.line);
+use tempdir::TempDir;
+use std::path::{Path, PathBuf};
+
+mod backup;
+mod cache;
+mod checkpoint;
+pub mod manifest;
+mod parallel;
+pub mod pass;
+pub mod predicate;
+pub mod transforms;
+mod uninline;
+
+pub use backup::handle_interrupts;
+pub use checkpoint::Cursor;
+pub use pass::{FnPass, Pass, Tester};
+
+/// Knobs for a single reduction run.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Remove items in ever smaller chunks before trying them one by one.
+    pub ddmin: bool,
+    /// Number of candidates to test concurrently.
+    pub jobs: usize,
+    /// Stop after this many rounds, even if the last one still made progress.
+    pub max_rounds: Option<usize>,
+    /// Write the result here and put the original file back, instead of
+    /// overwriting the original.
+    pub output: Option<PathBuf>,
+    /// Split the result back into the files its modules were inlined from.
+    /// With `output`, that's a directory to write them into.
+    pub split_modules: bool,
+    /// Keep the outcome of every candidate in this file, and skip the ones
+    /// already in there.
+    pub cache: Option<PathBuf>,
+    /// Every now and then, save how far the reduction has got to this file.
+    pub checkpoint: Option<PathBuf>,
+    /// Carry on from `checkpoint` instead of starting over.
+    pub resume: bool,
+    /// Paths of attributes the `remove-attrs` pass never removes, e.g.
+    /// `repr`.
+    pub keep_attrs: Vec<String>,
+    /// If not empty, the paths of the only attributes the `remove-attrs`
+    /// pass tries to remove.
+    pub only_attrs: Vec<String>,
+    /// Resolve `#[cfg]` and `#[cfg_attr]` for this configuration, with the
+    /// `eval-cfg` pass.
+    pub cfg: Option<transforms::eval_cfg::CfgSet>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            ddmin: false,
+            jobs: 1,
+            max_rounds: None,
+            output: None,
+            split_modules: false,
+            cache: None,
+            checkpoint: None,
+            resume: false,
+            keep_attrs: Vec::new(),
+            only_attrs: Vec::new(),
+            cfg: None,
+        }
+    }
+}
+
+/// Why a reduction couldn't be done.
+#[derive(Debug)]
+pub enum ReduceError {
+    /// The test didn't find the original input interesting.
+    NotInteresting(String),
+    /// The test stopped finding the input interesting once its modules were
+    /// inlined.
+    InlinedNotInteresting(String),
+    /// The test stopped finding the reduced file interesting, so it's flaky.
+    ReducedNotInteresting(String),
+    /// Some `mod foo;`s couldn't be found while inlining: their names, and
+    /// the files and lines they're declared at.
+    ModulesNotFound(Vec<(String, PathBuf, usize)>),
+    /// The modules couldn't be inlined for some other reason.
+    Inline(String),
+    /// The checkpoint to resume from couldn't be used.
+    Checkpoint(String),
+    /// Reading or writing one of the files involved failed.
+    Io(String, std::io::Error),
+}
+
+impl std::fmt::Display for ReduceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ReduceError::NotInteresting(msg) =>
+                write!(f, "run with initial input did not indicate success: {}", msg),
+            ReduceError::InlinedNotInteresting(msg) =>
+                write!(f, "run with modules inlined did not indicate success: {}", msg),
+            ReduceError::ReducedNotInteresting(msg) =>
+                write!(f, "run with reduced input did not indicate success: {}", msg),
+            ReduceError::ModulesNotFound(missing) => {
+                write!(f, "file not found")?;
+                for (modname, path, line) in missing {
+                    write!(f, "\n    mod {} @ {}:{}", modname, path.display(), line)?;
+                }
+                Ok(())
+            },
+            ReduceError::Inline(msg) => write!(f, "couldn't inline modules: {}", msg),
+            ReduceError::Checkpoint(msg) => write!(f, "couldn't resume from checkpoint: {}", msg),
+            ReduceError::Io(what, e) => write!(f, "{}: {}", what, e),
+        }
+    }
+}
+
+impl std::error::Error for ReduceError {}
+
+fn io_error(what: String) -> impl FnOnce(std::io::Error) -> ReduceError {
+    move |e| ReduceError::Io(what, e)
+}
+
+/// Reduces the file of a `Runnable` for as long as the result stays
+/// interesting, by running a list of passes over it until they stop making
+/// it smaller.
+pub struct Reducer<R> {
+    runnable: R,
+    options: Options,
+    passes: Option<Vec<Box<dyn Pass>>>,
+    progress: Box<dyn FnMut(&str)>,
+}
+
+impl<R: Runnable + Sync> Reducer<R> {
+    pub fn new(runnable: R) -> Reducer<R> {
+        Reducer { runnable, options: Options::default(), passes: None, progress: Box::new(|_| {}) }
+    }
+
+    pub fn options(mut self, options: Options) -> Reducer<R> {
+        self.options = options;
+        self
+    }
+
+    /// Run these passes each round, in this order, instead of
+    /// `transforms::default_passes`.
+    pub fn passes(mut self, passes: Vec<Box<dyn Pass>>) -> Reducer<R> {
+        self.passes = Some(passes);
+        self
+    }
+
+    /// Report each round and pass as they start, and where the results are
+    /// written, to `progress`. Nothing is reported by default.
+    pub fn progress<F: FnMut(&str) + 'static>(mut self, progress: F) -> Reducer<R> {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    /// Do the reduction, write out the result as configured in the
+    /// `Options`, and return it. If it fails once the file being reduced
+    /// has been overwritten, the original is put back.
+    pub fn reduce(self) -> Result<syn::File, ReduceError> {
+        let Reducer { runnable, options, passes, mut progress } = self;
+        let options = &options;
+        let mut passes = passes.unwrap_or_else(|| transforms::default_passes(options));
+
+        let resume = match (&options.checkpoint, options.resume) {
+            (Some(checkpoint), true) => match checkpoint::Checkpoint::load(checkpoint) {
+                Ok(resume) if resume.path == runnable.get_path() => Some(resume),
+                // Another entry point's, or that one's already finished.
+                Ok(_) => None,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(ReduceError::Checkpoint(format!("{}: {}", checkpoint.display(), e))),
+            },
+            _ => None,
+        };
+        if resume.is_some() {
+            // If we were killed while candidates were being written in place,
+            // the original is only left in the backup.
+            let orig = backup::orig_path(runnable.get_path());
+            if orig.exists() {
+                std::fs::copy(&orig, runnable.get_path())
+                    .map_err(io_error(format!("Couldn't restore {}", runnable.get_path().display())))?;
             }
-            std::process::exit(1);
-        },
-        Err(err) => unimplemented!("This wasn't supposed to happen. {:?}", err)
-    };
+        }
+
+        runnable.run_in(runnable.root(), runnable.get_path())
+            .map_err(ReduceError::NotInteresting)?;
+
+        let mut inlined_file = match InlinerBuilder::new()
+            .error_not_found(true)
+            .parse_and_inline_modules(runnable.get_path()) {
+            Ok(f) => f,
+            Err(InlineError::NotFound(missing)) => return Err(ReduceError::ModulesNotFound(
+                missing.into_iter().map(|(modname, loc)| (modname, loc.path, loc.line)).collect())),
+            Err(err) => return Err(ReduceError::Inline(format!("{:?}", err))),
+        };
+
+        // Needs to happen before the original is overwritten with candidates.
+        let mod_files = if options.split_modules {
+            uninline::mod_files(runnable.get_path())
+        } else {
+            Vec::new()
+        };
+
+        let cache = cache::Cache::new(&runnable.describe(), options.cache.as_deref())
+            .map_err(io_error("Couldn't open cache".to_owned()))?;
+        if let (Some(path), true) = (&options.cache, cache.len() > 0) {
+            progress(&format!("Loaded {} cached outcomes from {}", cache.len(), path.display()));
+        }
+
+        let checkpoints = checkpoint::Checkpoints::new(options.checkpoint.clone(), runnable.get_path(),
+                                                       file_source(&inlined_file));
+        let mut skip_to = None;
+        let mut round = 1;
+        if let Some(resume) = resume {
+            progress(&format!("Resuming round {} at {}", resume.round, resume.pass));
+            inlined_file = syn::parse_file(&resume.source)
+                .map_err(|e| ReduceError::Checkpoint(e.to_string()))?;
+            round = resume.round;
+            if passes.iter().any(|pass| pass.name() == resume.pass) {
+                skip_to = Some(resume.pass.clone());
+            }
+            checkpoints.resume(resume);
+        }
+
+        let write_candidate = |reduced_syn_file: &syn::File| {
+            let source = file_source(reduced_syn_file);
+            let path = runnable.candidate_path();
+            backup::write(path, &source)
+                .map_err(io_error(format!("Couldn't write {}", path.display())))?;
+            Ok(source)
+        };
+
+        // Passes can't be told to stop, so once something goes wrong every
+        // candidate is uninteresting until they've finished.
+        let failure = RefCell::new(None);
+        let fail = |e: ReduceError| {
+            let msg = e.to_string();
+            failure.borrow_mut().get_or_insert(e);
+            msg
+        };
+
+        let mut try_compile = |reduced_syn_file: &syn::File| {
+            if failure.borrow().is_some() {
+                return Err("Stopping after an error.".to_string());
+            }
+            // Written even if the outcome is known, as whatever was last
+            // interesting has to end up on disk.
+            let source = write_candidate(reduced_syn_file).map_err(fail)?;
+            let key = cache.key(&source);
+            let result = match cache.get(&key) {
+                Some(true) => Ok(()),
+                Some(false) => Err("Known to be uninteresting.".to_string()),
+                None => {
+                    checkpoints.tick().map_err(fail)?;
+                    let result = runnable.run();
+                    cache.insert(key, result.is_ok())
+                        .map_err(io_error("Couldn't write to cache".to_owned()))
+                        .map_err(fail)?;
+                    result
+                },
+            };
+            if result.is_ok() {
+                checkpoints.interesting(&source);
+            }
+            result
+        };
+
+        let backup = backup::Backup::new(runnable.get_path())
+            .map_err(io_error(format!("Couldn't back up {}", runnable.get_path().display())))?;
+        let restore = || backup.restore()
+            .map_err(io_error(format!("Couldn't restore {}", runnable.get_path().display())));
+        let restore_and_fail = |e: ReduceError| restore().and(Err(e));
+
+        // Write the inlined file:
+        if let Err(msg) = try_compile(&inlined_file) {
+            return restore_and_fail(failure.take().unwrap_or(ReduceError::InlinedNotInteresting(msg)));
+        }
+
+        let workers = if options.jobs > 1 {
+            let workers = match parallel::Workers::new(&runnable, options.jobs) {
+                Ok(workers) => workers,
+                Err(e) => return restore_and_fail(ReduceError::Io("Couldn't set up worker directories".to_owned(), e)),
+            };
+            progress(&format!("Testing up to {} candidates at a time", workers.jobs()));
+            Some(workers)
+        } else {
+            None
+        };
+        let mut try_batch = |candidates: &[syn::File]| {
+            if failure.borrow().is_some() {
+                return None;
+            }
+            let workers = workers.as_ref().expect("only used with workers");
+            let winner = checkpoints.tick()
+                .and_then(|()| workers.try_batch(candidates, &cache))
+                .and_then(|winner| match winner {
+                    Some(winner) => {
+                        checkpoints.interesting(&write_candidate(&candidates[winner])?);
+                        Ok(Some(winner))
+                    },
+                    None => Ok(None),
+                });
+            winner.map_err(fail).ok().flatten()
+        };
+
+        loop {
+            let before = checkpoints.start_round(round, file_size(&inlined_file));
+            progress(&format!("Round {}: {} bytes", round, before));
+
+            for pass in &mut passes {
+                match &skip_to {
+                    Some(resume) if resume != pass.name() => continue,
+                    Some(_) => skip_to = None,
+                    None => {},
+                }
+                if let Err(e) = checkpoints.start_pass(pass.name()) {
+                    return restore_and_fail(e);
+                }
+                progress(&pass.description());
+                let mut tester = pass::Tester {
+                    try_compile: &mut try_compile,
+                    try_batch: match workers {
+                        Some(_) => Some(&mut try_batch),
+                        None => None,
+                    },
+                    jobs: workers.as_ref().map_or(1, |workers| workers.jobs()),
+                    cursor: &checkpoints.cursor,
+                };
+                pass.run(&mut inlined_file, &mut tester);
+                if let Some(e) = failure.take() {
+                    return restore_and_fail(e);
+                }
+            }
+
+            let after = file_size(&inlined_file);
+            progress(&format!("Round {} done: {} -> {} bytes", round, before, after));
+            if after >= before {
+                break;
+            }
+            if options.max_rounds.is_some_and(|max| round >= max) {
+                progress(&format!("Stopping after {} rounds", round));
+                break;
+            }
+            round += 1;
+        }
 
-    let mut try_compile = |reduced_syn_file: &syn::File| {
-        let path = runnable.get_path();
-        let mut file = std::fs::File::create(path)
-            .unwrap_or_else(|_| panic!("{:?}", path));
-        write!(file, "{}", reduced_syn_file.into_token_stream()).unwrap();
-        runnable.run()
+        // Ensure a successful file is written:
+        if let Err(msg) = try_compile(&inlined_file) {
+            return restore_and_fail(failure.take().unwrap_or(ReduceError::ReducedNotInteresting(msg)));
+        }
+        progress(&cache.report());
+        checkpoints.finish();
+
+        if options.split_modules {
+            if options.output.is_some() {
+                restore()?;
+            }
+            write_modules(&runnable, options.output.as_deref(), &mod_files, inlined_file.clone(), &mut progress)?;
+            return Ok(inlined_file);
+        }
+
+        let result = options.output.as_deref().unwrap_or_else(|| runnable.get_path());
+        if result != runnable.candidate_path() {
+            std::fs::copy(runnable.candidate_path(), result)
+                .map_err(io_error(format!("Couldn't write {}", result.display())))?;
+        }
+        if options.output.is_some() {
+            restore()?;
+        }
+        format(result, &mut progress);
+        progress(&format!("Reduced file written to {}", result.display()));
+        Ok(inlined_file)
+    }
+}
+
+/// Reduce with the default passes, without reporting progress.
+pub fn reduce<R: Runnable + Sync>(runnable: R, options: &Options) -> Result<syn::File, ReduceError> {
+    Reducer::new(runnable).options(options.clone()).reduce()
+}
+
+/// Write `file` back out with the module layout it was inlined from, either
+/// over the original files or into the `output` directory.
+fn write_modules<R: Runnable>(runnable: &R, output: Option<&Path>, mod_files: &[uninline::ModFile], mut file: syn::File,
+                              progress: &mut dyn FnMut(&str)) -> Result<(), ReduceError> {
+    let entry = runnable.get_path();
+    let entry_dir = entry.parent().unwrap_or_else(|| Path::new(""));
+    let dest = |path: &Path| match output {
+        Some(output) => path.strip_prefix(entry_dir).ok().map(|rel| output.join(rel)),
+        None => Some(path.to_owned()),
     };
 
-    // Write the inlined file:
-    try_compile(&inlined_file).unwrap();
-
-    println!("Pruning items");
-    transforms::prune_items::prune_items(&mut inlined_file, &mut try_compile);
-    println!("Removing #[derive] attributes");
-    transforms::remove_derive_attrs::remove_derive_attrs(&mut inlined_file, &mut try_compile);
-    println!("Removing #[doc] attributes");
-    transforms::remove_doc_attrs::remove_doc_attrs(&mut inlined_file, &mut try_compile);
-    println!("Clearing block bodies - {{}}");
-    transforms::empty_blocks::empty_blocks(&mut inlined_file, &mut try_compile);
-    println!("Clearing block bodies - unimplemented");
-    transforms::clear_blocks::clear_blocks(&mut inlined_file, &mut try_compile);
-    println!("Removing pub");
-    transforms::privatiser::privatise_items(&mut inlined_file, &mut try_compile);
-
-    // Ensure a successful file is written:
-    try_compile(&inlined_file).unwrap();
-
-    if let Err(msg) = Command::new("cargo")
-        .args(vec!["fmt"])
-        .current_dir(&runnable.root())
-        .output() {
-        eprintln!("cargo fmt failed/not found so min unformatted. {}", msg);
-    }
-
-    //Put the original one back...
-    //let min = std::fs::read(runnable.get_path()).unwrap();
-
-//    let min_path = runnable.get_path()
-//        .with_extension("rs");
-//    println!("writing min to {:?}", &min_path);
-//    std::fs::copy(runnable.get_path(), min_path).unwrap();
-//
-//    //std::fs::write(min_path, min).unwrap();
-//
-//    std::fs::write(runnable.get_path(), original).unwrap();
+    // Modules from outside the entry's directory stay inline.
+    let mods: Vec<_> = mod_files.iter()
+        .filter_map(|mod_file| Some((mod_file, dest(&mod_file.file)?)))
+        .collect();
+    let files = uninline::split(&mut file, &mods);
+
+    if output.is_none() {
+        for (mod_file, _) in &mods {
+            std::fs::copy(&mod_file.file, backup::orig_path(&mod_file.file))
+                .map_err(io_error(format!("Couldn't back up {}", mod_file.file.display())))?;
+            if !files.iter().any(|(path, _)| *path == mod_file.file) {
+                progress(&format!("Removing {}", mod_file.file.display()));
+                std::fs::remove_file(&mod_file.file)
+                    .map_err(io_error(format!("Couldn't remove {}", mod_file.file.display())))?;
+            }
+        }
+    }
+
+    let root = dest(entry).expect("entry is in its own directory");
+    let files: Vec<_> = std::iter::once((root, file)).chain(files).collect();
+    for (path, contents) in &files {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(io_error(format!("Couldn't create {}", parent.display())))?;
+        }
+        std::fs::write(path, contents.into_token_stream().to_string())
+            .map_err(io_error(format!("Couldn't write {}", path.display())))?;
+    }
+    // Only once they're all there, as `rustfmt` follows `mod foo;`.
+    for (path, _) in &files {
+        format(path, progress);
+        progress(&format!("Reduced file written to {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Clean up the output with `rustfmt`, if it's around.
+fn format(path: &Path, progress: &mut dyn FnMut(&str)) {
+    let mut rustfmt = Command::new("rustfmt");
+    if let Some(edition) = edition(path) {
+        rustfmt.args(["--edition", &edition]);
+    }
+    match rustfmt.arg(path).output() {
+        Ok(ref out) if out.status.success() => {},
+        Ok(out) => progress(&format!("rustfmt failed so min unformatted. {}", String::from_utf8_lossy(&out.stderr))),
+        Err(msg) => progress(&format!("rustfmt not found so min unformatted. {}", msg)),
+    }
+}
+
+/// The edition of the package `path` belongs to, from the nearest
+/// `Cargo.toml` above it, or from its workspace's if it's inherited from
+/// there. Without `--edition`, `rustfmt` picks 2015, which is also what
+/// Cargo assumes if none is set.
+fn edition(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let mut inherited = false;
+    for dir in path.ancestors().skip(1) {
+        let manifest = match std::fs::read_to_string(dir.join("Cargo.toml")) {
+            Ok(manifest) => manifest.parse::<toml::Value>().ok()?,
+            Err(_) => continue,
+        };
+        let edition = if inherited {
+            match manifest.get("workspace") {
+                Some(workspace) => workspace.get("package")?.get("edition")?,
+                None => continue,
+            }
+        } else {
+            manifest.get("package")?.get("edition")?
+        };
+        match edition.as_str() {
+            Some(edition) => return Some(edition.to_owned()),
+            None if edition.get("workspace").and_then(toml::Value::as_bool) == Some(true) => inherited = true,
+            None => return None,
+        }
+    }
+    None
+}
+
+fn file_size(file: &syn::File) -> usize {
+    file_source(file).len()
+}
+
+fn file_source(file: &syn::File) -> String {
+    file.into_token_stream().to_string()
 }
 
 pub trait Runnable {
     fn root(&self) -> &Path;
     fn get_path(&self) -> &Path;
-    fn run(&self) -> Result<(), String>;
+
+    /// Where candidates are written before calling `run`. Defaults to the
+    /// file being reduced itself.
+    fn candidate_path(&self) -> &Path {
+        self.get_path()
+    }
+
+    /// Identifies the test, so cached outcomes of a different one aren't
+    /// reused.
+    fn describe(&self) -> String {
+        self.get_path().display().to_string()
+    }
+
+    fn run(&self) -> Result<(), String> {
+        self.run_in(self.root(), self.candidate_path())
+    }
+
+    /// Run against a copy made by `copy_to`, rooted at `root` with the
+    /// candidate at `path`, instead of the original.
+    fn run_in(&self, root: &Path, path: &Path) -> Result<(), String>;
+
+    /// Copy everything needed to run the test into `dir`. Returns the new
+    /// root and the new location of the file being reduced.
+    fn copy_to(&self, dir: &Path) -> std::io::Result<(PathBuf, PathBuf)> {
+        let root = dir.join("root");
+        parallel::copy_dir(self.root(), &root)?;
+        let relative = self.get_path().strip_prefix(self.root()).map_err(|_| std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} isn't under {}", self.get_path().display(), self.root().display()),
+        ))?;
+        Ok((root.clone(), root.join(relative)))
+    }
 }
 
 pub struct TestScript<'me>{
     pub cmd: Vec<OsString>,
-    pub path: &'me std::path::Path
+    pub path: &'me std::path::Path,
+    pub predicate: predicate::Predicate,
+    /// Candidates go in here, so `path` is only written once we're done.
+    _dir: TempDir,
+    candidate: PathBuf,
+}
+
+impl<'me> TestScript<'me> {
+    pub fn new(cmd: Vec<OsString>, predicate: predicate::Predicate, path: &'me Path) -> Result<TestScript<'me>, ReduceError> {
+        let invalid = |msg: &str| ReduceError::Io(path.display().to_string(),
+                                                   std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+        if cmd.is_empty() {
+            return Err(invalid("no command to test it with"));
+        }
+        let name = path.file_name().ok_or_else(|| invalid("not a file"))?;
+        let dir = TempDir::new("rust-reduce").map_err(io_error("Couldn't create temporary directory".to_owned()))?;
+        let candidate = dir.path().join(name);
+        Ok(TestScript { cmd, path, predicate, _dir: dir, candidate })
+    }
 }
 
 impl <'me> Runnable for TestScript<'me> {
     fn root(&self) -> &Path {
-        unimplemented!()
+        self.path.parent().expect("checked by new")
     }
 
     fn get_path(&self) -> &Path {
-        &self.path
+        self.path
     }
 
-    fn run(&self) -> Result<(), String> {
-        let (cmd, args) = self.cmd.split_first()
-            .expect("validated");
-
-        match Command::new(cmd)
-            .args(args)
-            .arg(&self.path)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            {
-                Ok(ref stat) if stat.success() => Ok(()),
-                _ => Err("Exit code was non-zero.".to_string())
-            }
+    fn candidate_path(&self) -> &Path {
+        &self.candidate
+    }
+
+    fn describe(&self) -> String {
+        format!("{:?} {:?}", self.cmd, self.predicate)
+    }
+
+    fn run_in(&self, _root: &Path, path: &Path) -> Result<(), String> {
+        self.predicate.run(&self.cmd, |cmd| {
+            let (cmd, args) = cmd.split_first()
+                .expect("checked by new");
+            let mut command = Command::new(cmd);
+            command.args(args)
+                .arg(path);
+            command
+        })
+    }
+
+    /// The candidate is self-contained, so only the file itself is copied.
+    fn copy_to(&self, dir: &Path) -> std::io::Result<(PathBuf, PathBuf)> {
+        let path = dir.join(self.path.file_name().expect("checked by new"));
+        std::fs::copy(self.path, &path)?;
+        Ok((dir.to_owned(), path))
     }
 }
\ No newline at end of file
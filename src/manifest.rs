@@ -0,0 +1,289 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Reduce the crate graph: try removing workspace members and dependencies
+//! from the `Cargo.toml` files.
+
+use std::path::{Path, PathBuf};
+
+use toml_edit::{Document, Item, TableLike, Value};
+
+use crate::backup::{self, Backup};
+use crate::Runnable;
+
+struct Manifest {
+    path: PathBuf,
+    /// The `workspace.members` entry this manifest was found through.
+    member: Option<String>,
+    /// What's currently on disk, known to be interesting.
+    text: String,
+    /// The same, parsed so it can be changed without losing its formatting.
+    value: Document,
+    backup: Backup,
+}
+
+impl Manifest {
+    fn load(path: PathBuf, member: Option<String>) -> std::io::Result<Manifest> {
+        let text = std::fs::read_to_string(&path)?;
+        let value = text.parse()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let backup = Backup::new(&path)?;
+        Ok(Manifest { path, member, text, value, backup })
+    }
+
+    /// Apply `change` and keep it if the test still passes. `change` returns
+    /// false if there was nothing to change.
+    fn try_change<R: Runnable, F: FnOnce(&mut Document) -> bool>(&mut self, runnable: &R, change: F) -> std::io::Result<bool> {
+        let mut candidate = self.value.clone();
+        if !change(&mut candidate) {
+            return Ok(false);
+        }
+        let text = candidate.to_string();
+        self.write(&text)?;
+        if runnable.run().is_ok() {
+            self.value = candidate;
+            self.text = text;
+            Ok(true)
+        } else {
+            self.write(&self.text)?;
+            Ok(false)
+        }
+    }
+
+    /// Try removing each key of the table at `table`.
+    fn remove_each_key<R: Runnable>(&mut self, runnable: &R, table: &[String], what: &str,
+                                    progress: &mut dyn FnMut(&str)) -> std::io::Result<()> {
+        for name in table_keys(&self.value, table) {
+            let removed = self.try_change(runnable, |value| {
+                let removed = table_mut(value, table)
+                    .and_then(|table| table.remove(&name))
+                    .is_some();
+                remove_empty_tables(value, table);
+                removed
+            })?;
+            if removed {
+                progress(&format!("Removed {} {} from {}", what, name, self.path.display()));
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, text: &str) -> std::io::Result<()> {
+        backup::write(&self.path, text)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("Couldn't write {}: {}", self.path.display(), e)))
+    }
+}
+
+/// The workspace's `Cargo.toml` files, backed up to `.orig` before they're
+/// changed.
+pub struct Manifests {
+    manifests: Vec<Manifest>,
+    progress: Box<dyn FnMut(&str)>,
+}
+
+impl Manifests {
+    /// Load `root/Cargo.toml` and the manifests of any workspace members.
+    pub fn load(root: &Path) -> std::io::Result<Manifests> {
+        let workspace = Manifest::load(root.join("Cargo.toml"), None)?;
+        let mut manifests = Vec::new();
+        for member in members(&workspace.value) {
+            for dir in expand_member(root, &member)? {
+                manifests.push(Manifest::load(dir.join("Cargo.toml"), Some(member.clone()))?);
+            }
+        }
+        manifests.insert(0, workspace);
+        Ok(Manifests { manifests, progress: Box::new(|_| {}) })
+    }
+
+    /// Report what's removed, and where the results are written, to
+    /// `progress`.
+    pub fn progress<F: FnMut(&str) + 'static>(mut self, progress: F) -> Manifests {
+        self.progress = Box::new(progress);
+        self
+    }
+
+    /// Try removing each workspace member.
+    pub fn reduce_members<R: Runnable>(&mut self, runnable: &R) -> std::io::Result<()> {
+        let mut index = 0;
+        while let Some(member) = members(&self.manifests[0].value).into_iter().nth(index) {
+            let removed = self.manifests[0].try_change(runnable, |value| {
+                value["workspace"]["members"].as_array_mut()
+                    .map(|members| members.remove(index))
+                    .is_some()
+            })?;
+            if removed {
+                (self.progress)(&format!("Removed workspace member {}", member));
+                self.manifests.retain(|manifest| manifest.member.as_ref() != Some(&member));
+            } else {
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Try removing each dependency of each manifest, of every kind and
+    /// including target-specific ones.
+    pub fn reduce_dependencies<R: Runnable>(&mut self, runnable: &R) -> std::io::Result<()> {
+        for manifest in &mut self.manifests {
+            for table in dependency_tables(&manifest.value, DEPENDENCY_KINDS) {
+                manifest.remove_each_key(runnable, &table, "dependency", &mut self.progress)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Try removing each `[features]` entry, then each feature enabled on a
+    /// dependency.
+    pub fn reduce_features<R: Runnable>(&mut self, runnable: &R) -> std::io::Result<()> {
+        for manifest in &mut self.manifests {
+            manifest.remove_each_key(runnable, &["features".to_string()], "feature", &mut self.progress)?;
+
+            for table in dependency_tables(&manifest.value, DEPENDENCY_KINDS) {
+                for name in table_keys(&manifest.value, &table) {
+                    let mut index = 0;
+                    loop {
+                        let feature = path_get(&manifest.value, &table)
+                            .and_then(|deps| deps.get(&name))
+                            .and_then(|dep| dep.get("features"))
+                            .and_then(Item::as_array)
+                            .and_then(|features| features.get(index))
+                            .and_then(Value::as_str)
+                            .map(ToOwned::to_owned);
+                        let feature = match feature {
+                            Some(feature) => feature,
+                            None => break,
+                        };
+                        let removed = manifest.try_change(runnable, |value| {
+                            table_mut(value, &table)
+                                .and_then(|deps| deps.get_mut(&name))
+                                .and_then(|dep| dep.get_mut("features"))
+                                .and_then(Item::as_array_mut)
+                                .map(|features| features.remove(index))
+                                .is_some()
+                        })?;
+                        if removed {
+                            (self.progress)(&format!("Removed feature {} of dependency {} from {}",
+                                                     feature, name, manifest.path.display()));
+                        } else {
+                            index += 1;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Try removing each `[profile.*]` section.
+    pub fn reduce_profiles<R: Runnable>(&mut self, runnable: &R) -> std::io::Result<()> {
+        for manifest in &mut self.manifests {
+            manifest.remove_each_key(runnable, &["profile".to_string()], "profile", &mut self.progress)?;
+        }
+        Ok(())
+    }
+
+    /// Put the original manifests back, and write the reduced ones next to
+    /// them with the `.min` suffix.
+    pub fn write_min(mut self) -> std::io::Result<()> {
+        for manifest in self.manifests {
+            let mut min = manifest.path.as_os_str().to_owned();
+            min.push(".min");
+            std::fs::write(&min, &manifest.text)?;
+            manifest.backup.restore()?;
+            (self.progress)(&format!("Reduced manifest written to {}", PathBuf::from(min).display()));
+        }
+        Ok(())
+    }
+}
+
+const DEPENDENCY_KINDS: &[&str] = &["dependencies", "dev-dependencies", "build-dependencies"];
+
+fn members(workspace: &Document) -> Vec<String> {
+    workspace.get("workspace")
+        .and_then(|workspace| workspace.get("members"))
+        .and_then(Item::as_array)
+        .map(|members| members.iter().filter_map(Value::as_str).map(ToOwned::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Directories matching a `workspace.members` entry. Only trailing `/*`
+/// globs are supported.
+pub fn expand_member(root: &Path, member: &str) -> std::io::Result<Vec<PathBuf>> {
+    if let Some(parent) = member.strip_suffix("/*") {
+        let mut dirs: Vec<_> = std::fs::read_dir(root.join(parent))?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.join("Cargo.toml").exists())
+            .collect();
+        dirs.sort();
+        Ok(dirs)
+    } else {
+        Ok(vec![root.join(member)])
+    }
+}
+
+/// Paths to the tables of the given dependency `kinds`, both top-level and
+/// under `[target.'cfg(...)']`.
+fn dependency_tables(manifest: &Document, kinds: &[&str]) -> Vec<Vec<String>> {
+    let mut tables = Vec::new();
+    for kind in kinds {
+        if manifest.get(kind).is_some_and(Item::is_table_like) {
+            tables.push(vec![kind.to_string()]);
+        }
+    }
+    if let Some(targets) = manifest.get("target").and_then(Item::as_table_like) {
+        for (target, value) in targets.iter() {
+            for kind in kinds {
+                if value.get(kind).is_some_and(Item::is_table_like) {
+                    tables.push(vec!["target".to_string(), target.to_owned(), kind.to_string()]);
+                }
+            }
+        }
+    }
+    tables
+}
+
+fn path_get<'v>(value: &'v Document, path: &[String]) -> Option<&'v Item> {
+    path.iter().try_fold(value.as_item(), |value, key| value.get(key))
+}
+
+fn table_mut<'v>(value: &'v mut Document, path: &[String]) -> Option<&'v mut dyn TableLike> {
+    path.iter().try_fold(value.as_item_mut(), |value, key| value.get_mut(key))?.as_table_like_mut()
+}
+
+/// Remove the table at `path` if it's empty, and then its parents if that
+/// leaves them empty.
+fn remove_empty_tables(value: &mut Document, path: &[String]) {
+    for len in (1..=path.len()).rev() {
+        let (key, parent) = path[..len].split_last().expect("len > 0");
+        let parent = match table_mut(value, parent) {
+            Some(parent) => parent,
+            None => return,
+        };
+        if !parent.get(key).and_then(Item::as_table_like).is_some_and(|table| table.is_empty()) {
+            return;
+        }
+        parent.remove(key);
+    }
+}
+
+fn table_keys(value: &Document, path: &[String]) -> Vec<String> {
+    path_get(value, path)
+        .and_then(Item::as_table_like)
+        .map(|table| table.iter().map(|(key, _)| key.to_owned()).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,118 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Evaluate several candidates at once, each in its own copy of the crate.
+
+use std::path::{Path, PathBuf};
+
+use quote::ToTokens;
+use tempdir::TempDir;
+
+use crate::cache::Cache;
+use crate::{ReduceError, Runnable};
+
+struct Worker {
+    _dir: TempDir,
+    root: PathBuf,
+    path: PathBuf,
+}
+
+pub(crate) struct Workers<'r, R> {
+    runnable: &'r R,
+    workers: Vec<Worker>,
+}
+
+impl<'r, R: Runnable + Sync> Workers<'r, R> {
+    pub(crate) fn new(runnable: &'r R, jobs: usize) -> std::io::Result<Self> {
+        let mut workers = Vec::with_capacity(jobs);
+        for _ in 0..jobs {
+            let dir = TempDir::new("rust-reduce")?;
+            let (root, path) = runnable.copy_to(dir.path())?;
+            workers.push(Worker { _dir: dir, root, path });
+        }
+        Ok(Workers { runnable, workers })
+    }
+
+    pub(crate) fn jobs(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Run every candidate concurrently and return the index of the first
+    /// interesting one, like testing them in order would. Candidates in
+    /// `cache` aren't run again.
+    pub(crate) fn try_batch(&self, candidates: &[syn::File], cache: &Cache) -> Result<Option<usize>, ReduceError> {
+        assert!(candidates.len() <= self.workers.len());
+        let runnable = self.runnable;
+        // syn types aren't `Send`, so print them before handing them out.
+        let sources: Vec<String> = candidates.iter()
+            .map(|candidate| candidate.into_token_stream().to_string())
+            .collect();
+        let keys: Vec<_> = sources.iter().map(|source| cache.key(source)).collect();
+        let known: Vec<_> = keys.iter().map(|key| cache.get(key)).collect();
+        let ran: Vec<Option<Result<bool, ReduceError>>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = sources.iter().zip(&known).zip(&self.workers)
+                .map(|((source, known), worker)| known.is_none().then(|| scope.spawn(move || {
+                    std::fs::write(&worker.path, source)
+                        .map_err(|e| ReduceError::Io(format!("Couldn't write {}", worker.path.display()), e))?;
+                    Ok(runnable.run_in(&worker.root, &worker.path).is_ok())
+                })))
+                .collect();
+            handles.into_iter()
+                .map(|handle| handle.map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))))
+                .collect()
+        });
+        let mut interesting = Vec::with_capacity(ran.len());
+        for ((key, known), ran) in keys.into_iter().zip(known).zip(ran) {
+            interesting.push(match ran {
+                Some(ran) => {
+                    let ran = ran?;
+                    cache.insert(key, ran)
+                        .map_err(|e| ReduceError::Io("Couldn't write to cache".to_owned(), e))?;
+                    ran
+                },
+                None => known == Some(true),
+            });
+        }
+
+        Ok(interesting.iter().position(|interesting| *interesting))
+    }
+}
+
+/// Copy the crate at `from` into `to`, leaving out its `target` directory
+/// and VCS metadata.
+pub(crate) fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    copy_tree(from, to, true)
+}
+
+/// Only at the `top` are `target` and `.git` skipped, as further down
+/// they may well be modules.
+fn copy_tree(from: &Path, to: &Path, top: bool) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if top && (name == "target" || name == ".git") {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &to.join(&name), false)?;
+        } else {
+            std::fs::copy(entry.path(), to.join(&name))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+//! The interface between `Reducer` and the passes it runs.
+
+use crate::checkpoint::Cursor;
+
+/// One kind of reduction, run once per round.
+///
+/// A pass makes a change to the file, asks the `Tester` whether the result
+/// is still interesting, and keeps or reverts the change accordingly, until
+/// it runs out of changes to try.
+pub trait Pass {
+    /// Identifies the pass, e.g. in checkpoints. Should be unique.
+    fn name(&self) -> &str;
+
+    /// Reported as progress when the pass starts.
+    fn description(&self) -> String {
+        self.name().to_owned()
+    }
+
+    fn run(&mut self, file: &mut syn::File, tester: &mut Tester);
+}
+
+type TryBatch<'t> = &'t mut dyn FnMut(&[syn::File]) -> Option<usize>;
+
+/// Tests candidates for a pass.
+pub struct Tester<'t> {
+    pub(crate) try_compile: &'t mut dyn FnMut(&syn::File) -> Result<(), String>,
+    pub(crate) try_batch: Option<TryBatch<'t>>,
+    pub(crate) jobs: usize,
+    pub(crate) cursor: &'t Cursor,
+}
+
+impl<'t> Tester<'t> {
+    /// Whether `file` is interesting. Nothing is kept: the pass has to leave
+    /// the last interesting candidate in the file it was given, as that's
+    /// what gets written out at the end.
+    pub fn test(&mut self, file: &syn::File) -> Result<(), String> {
+        (self.try_compile)(file)
+    }
+
+    /// Test up to `jobs()` candidates, concurrently if possible, and return
+    /// the index of the first interesting one, if any.
+    pub fn test_batch(&mut self, candidates: &[syn::File]) -> Option<usize> {
+        match &mut self.try_batch {
+            Some(try_batch) => try_batch(candidates),
+            None => candidates.iter().position(|candidate| (self.try_compile)(candidate).is_ok()),
+        }
+    }
+
+    /// How many candidates `test_batch` can test at once.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Where the pass should record how far it's got, and pick up from
+    /// when resuming.
+    pub fn cursor(&self) -> &'t Cursor {
+        self.cursor
+    }
+}
+
+/// A pass written against the plain `try_compile` contract, which ignores
+/// the cursor and always starts from the beginning.
+pub struct FnPass<F> {
+    name: String,
+    run: F,
+}
+
+impl<F> FnPass<F>
+    where F: FnMut(&mut syn::File, &mut dyn FnMut(&syn::File) -> Result<(), String>)
+{
+    pub fn new(name: &str, run: F) -> FnPass<F> {
+        FnPass { name: name.to_owned(), run }
+    }
+}
+
+impl<F> Pass for FnPass<F>
+    where F: FnMut(&mut syn::File, &mut dyn FnMut(&syn::File) -> Result<(), String>)
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&mut self, file: &mut syn::File, tester: &mut Tester) {
+        (self.run)(file, &mut |candidate| tester.test(candidate))
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Decide whether a test run was interesting.
+
+use std::ffi::OsString;
+use std::io::Read;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use regex::Regex;
+use wait_timeout::ChildExt;
+
+/// Which of the output streams patterns are matched against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+    Both,
+}
+
+impl std::str::FromStr for Stream {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "stdout" => Ok(Stream::Stdout),
+            "stderr" => Ok(Stream::Stderr),
+            "both" => Ok(Stream::Both),
+            _ => Err(format!("unknown stream `{}`, expected stdout, stderr or both", s)),
+        }
+    }
+}
+
+/// Patterns the output must (or must not) match.
+#[derive(Clone, Debug)]
+pub struct OutputPredicate {
+    /// Patterns that must match. All of them, or any one with `any`.
+    pub patterns: Vec<Regex>,
+    /// Patterns none of which may match.
+    pub forbidden: Vec<Regex>,
+    pub any: bool,
+    pub stream: Stream,
+}
+
+impl OutputPredicate {
+    /// Output must contain `text` on either stream.
+    pub fn contains(text: &str) -> OutputPredicate {
+        OutputPredicate {
+            patterns: vec![Regex::new(&regex::escape(text)).expect("escaped")],
+            forbidden: Vec::new(),
+            any: false,
+            stream: Stream::Both,
+        }
+    }
+
+    pub fn check(&self, stdout: &str, stderr: &str) -> Result<(), String> {
+        let matches = |regex: &Regex| match self.stream {
+            Stream::Stdout => regex.is_match(stdout),
+            Stream::Stderr => regex.is_match(stderr),
+            Stream::Both => regex.is_match(stdout) || regex.is_match(stderr),
+        };
+
+        let missing: Vec<_> = self.patterns.iter().filter(|regex| !matches(regex)).collect();
+        let found = if self.any {
+            missing.len() < self.patterns.len() || self.patterns.is_empty()
+        } else {
+            missing.is_empty()
+        };
+        if !found {
+            return Err(format!("\nCould not find {} in:\nout:\n{}\nerr:\n{}",
+                               join(&missing), stdout, stderr));
+        }
+        if let Some(regex) = self.forbidden.iter().find(|regex| matches(regex)) {
+            return Err(format!("\nFound forbidden `{}` in:\nout:\n{}\nerr:\n{}",
+                               regex, stdout, stderr));
+        }
+        Ok(())
+    }
+}
+
+fn join(regexes: &[&Regex]) -> String {
+    regexes.iter().map(|regex| format!("`{}`", regex)).collect::<Vec<_>>().join(", ")
+}
+
+/// What the command's exit status has to look like.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExitPredicate {
+    /// Anything goes, e.g. when only the output matters.
+    Any,
+    /// Exit code 0.
+    Success,
+    Code(i32),
+    /// Killed by this signal, or by any signal if `None`.
+    Signal(Option<i32>),
+    /// Exits differently from this other command, run the same way.
+    DiffersFrom(Vec<OsString>),
+    /// Still running when the timeout is up, e.g. a compiler hang.
+    TimesOut,
+}
+
+impl ExitPredicate {
+    /// Parse a signal name like `SIGSEGV` or `segv`, a signal number, or
+    /// `any`.
+    pub fn signal(name: &str) -> Result<ExitPredicate, String> {
+        if name.eq_ignore_ascii_case("any") {
+            return Ok(ExitPredicate::Signal(None));
+        }
+        if let Ok(number) = name.parse() {
+            return Ok(ExitPredicate::Signal(Some(number)));
+        }
+        let upper = name.to_ascii_uppercase();
+        let short = upper.strip_prefix("SIG").unwrap_or(&upper);
+        SIGNALS.iter()
+            .find(|(name, _)| *name == short)
+            .map(|&(_, number)| ExitPredicate::Signal(Some(number)))
+            .ok_or_else(|| format!("unknown signal `{}`", name))
+    }
+
+    /// Check how the command ended, running the other one with `run_other`
+    /// if it needs comparing.
+    fn check<F: FnOnce(&[OsString]) -> Result<Ending, String>>(&self, ending: Ending, run_other: F) -> Result<(), String> {
+        let status = match (self, ending) {
+            (ExitPredicate::TimesOut, Ending::TimedOut) => return Ok(()),
+            (ExitPredicate::DiffersFrom(other), ending) => {
+                let other_ending = run_other(other)?;
+                if ending == other_ending {
+                    return Err(format!("Both commands ended with {}.", ending));
+                }
+                return Ok(());
+            },
+            (_, Ending::TimedOut) => return Err(format!("Expected {}, but the command timed out.", self)),
+            (_, Ending::Exited(status)) => status,
+        };
+        let ok = match self {
+            ExitPredicate::Any => true,
+            ExitPredicate::Success => status.success(),
+            ExitPredicate::Code(code) => status.code() == Some(*code),
+            ExitPredicate::Signal(expected) => match (signal(status), expected) {
+                (Some(_), None) => true,
+                (Some(signal), Some(expected)) => signal == *expected,
+                (None, _) => false,
+            },
+            ExitPredicate::DiffersFrom(_) | ExitPredicate::TimesOut => false,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(format!("Expected {}, but the command ended with {}.", self, ending))
+        }
+    }
+}
+
+impl std::fmt::Display for ExitPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExitPredicate::Any => write!(f, "any exit status"),
+            ExitPredicate::Success => write!(f, "exit code 0"),
+            ExitPredicate::Code(code) => write!(f, "exit code {}", code),
+            ExitPredicate::Signal(None) => write!(f, "a signal"),
+            ExitPredicate::Signal(Some(signal)) => write!(f, "signal {}", signal),
+            ExitPredicate::DiffersFrom(other) => write!(f, "a different exit status than {:?}", other),
+            ExitPredicate::TimesOut => write!(f, "a timeout"),
+        }
+    }
+}
+
+/// Everything a run has to satisfy to be interesting.
+#[derive(Clone, Debug)]
+pub struct Predicate {
+    pub exit: ExitPredicate,
+    /// Only if set is the output captured at all.
+    pub output: Option<OutputPredicate>,
+    /// Kill the command (and anything it started) once it's been running
+    /// this long.
+    pub timeout: Option<Duration>,
+}
+
+impl Predicate {
+    /// Run `cmd` and check the outcome. `command` turns a command line into
+    /// something ready to spawn, adding the path, working directory etc.
+    pub fn run<F: Fn(&[OsString]) -> Command>(&self, cmd: &[OsString], command: F) -> Result<(), String> {
+        let (ending, stdout, stderr) = execute(&mut command(cmd), self.output.is_some(), self.timeout)
+            .map_err(|e| format!("Failed to execute {:?}: {}", cmd, e))?;
+        let run_other = |other: &[OsString]| {
+            execute(&mut command(other), false, self.timeout)
+                .map(|(ending, _, _)| ending)
+                .map_err(|e| format!("Failed to execute {:?}: {}", other, e))
+        };
+
+        // Checked last, as it means running another command.
+        let differs = matches!(self.exit, ExitPredicate::DiffersFrom(_));
+        if !differs {
+            self.exit.check(ending, run_other)?;
+        }
+        if let Some(predicate) = &self.output {
+            predicate.check(&stdout, &stderr)?;
+        }
+        if differs {
+            self.exit.check(ending, run_other)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<OutputPredicate> for Predicate {
+    /// Only look at the output, whatever the exit status.
+    fn from(output: OutputPredicate) -> Predicate {
+        Predicate { exit: ExitPredicate::Any, output: Some(output), timeout: None }
+    }
+}
+
+/// How a run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Ending {
+    Exited(ExitStatus),
+    TimedOut,
+}
+
+impl std::fmt::Display for Ending {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let status = match self {
+            Ending::Exited(status) => *status,
+            Ending::TimedOut => return write!(f, "a timeout"),
+        };
+        match (status.code(), signal(status)) {
+            (Some(code), _) => write!(f, "exit code {}", code),
+            (None, Some(signal)) => write!(f, "signal {}", signal),
+            (None, None) => write!(f, "{}", status),
+        }
+    }
+}
+
+/// Run `command` to completion or until `timeout`, returning its output if
+/// it's to be captured.
+fn execute(command: &mut Command, capture: bool, timeout: Option<Duration>) -> std::io::Result<(Ending, String, String)> {
+    let stdio = || if capture { Stdio::piped() } else { Stdio::null() };
+    command.stdout(stdio()).stderr(stdio());
+    let (mut child, _group) = if timeout.is_some() {
+        own_process_group(command);
+        // Registered before the lock is released, so an interrupt can't
+        // sneak in between.
+        let mut groups = groups();
+        let child = command.spawn()?;
+        groups.push(child.id());
+        let group = Group(child.id());
+        (child, Some(group))
+    } else {
+        (command.spawn()?, None)
+    };
+
+    // Read both pipes while waiting, or a chatty child could block on them.
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let ending = match timeout {
+        Some(timeout) => match child.wait_timeout(timeout)? {
+            Some(status) => Ending::Exited(status),
+            None => {
+                kill_tree(&mut child);
+                child.wait()?;
+                Ending::TimedOut
+            },
+        },
+        None => Ending::Exited(child.wait()?),
+    };
+
+    let collect = |reader: Option<std::thread::JoinHandle<Vec<u8>>>| reader
+        .map(|reader| String::from_utf8_lossy(&reader.join().expect("reader doesn't panic")).into_owned())
+        .unwrap_or_default();
+    Ok((ending, collect(stdout), collect(stderr)))
+}
+
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+/// The process groups of commands running with a timeout. Ctrl-C doesn't
+/// reach them, see `own_process_group`.
+static GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+fn groups() -> MutexGuard<'static, Vec<u32>> {
+    GROUPS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// In `GROUPS` for as long as it's around.
+struct Group(u32);
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        groups().retain(|&id| id != self.0);
+    }
+}
+
+/// Kill the commands still running in their own process group, as we're
+/// about to exit. Nothing else gets started afterwards.
+pub(crate) fn kill_running() {
+    let groups = groups();
+    for &id in groups.iter() {
+        kill_group(id);
+    }
+    std::mem::forget(groups);
+}
+
+/// So that everything it starts can be killed along with it.
+#[cfg(unix)]
+fn own_process_group(command: &mut Command) {
+    std::os::unix::process::CommandExt::process_group(command, 0);
+}
+
+#[cfg(not(unix))]
+fn own_process_group(_command: &mut Command) {}
+
+#[cfg(unix)]
+fn kill_tree(child: &mut Child) {
+    // The child leads its own process group, see `own_process_group`.
+    kill_group(child.id());
+}
+
+#[cfg(not(unix))]
+fn kill_tree(child: &mut Child) {
+    let _ = child.kill();
+}
+
+#[cfg(unix)]
+fn kill_group(id: u32) {
+    unsafe {
+        libc::kill(-(id as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Without process groups, Ctrl-C reaches the children anyway.
+#[cfg(not(unix))]
+fn kill_group(_id: u32) {}
+
+#[cfg(unix)]
+fn signal(status: ExitStatus) -> Option<i32> {
+    std::os::unix::process::ExitStatusExt::signal(&status)
+}
+
+#[cfg(not(unix))]
+fn signal(_status: ExitStatus) -> Option<i32> {
+    None
+}
+
+#[cfg(unix)]
+const SIGNALS: &[(&str, i32)] = &[
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("ILL", libc::SIGILL),
+    ("KILL", libc::SIGKILL),
+    ("SEGV", libc::SIGSEGV),
+    ("TERM", libc::SIGTERM),
+    ("TRAP", libc::SIGTRAP),
+];
+
+#[cfg(not(unix))]
+const SIGNALS: &[(&str, i32)] = &[];
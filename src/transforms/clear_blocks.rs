@@ -17,17 +17,19 @@
 
 /// Try to replace each block with `{ unimplemented!() }`, similar to `rustc`'s
 /// every body loops printer.
-
 use std::mem;
 
 use syn::visit_mut::*;
 use quote::quote;
 
-pub fn clear_blocks<F: FnMut(&syn::File) -> Result<(),String>>(file: &mut syn::File, mut try_compile: F) {
+use crate::checkpoint::Cursor;
+
+pub fn clear_blocks<F: FnMut(&syn::File) -> Result<(),String>>(file: &mut syn::File, cursor: &Cursor, mut try_compile: F) {
+    let [target_index] = cursor.start([1]);
     let mut visitor = BlockVisitor {
         backup: None,
         cur_index: 0,
-        target_index: 1,
+        target_index,
         unimplemented: syn::parse2(quote!( { unimplemented!() } )).unwrap()
     };
 
@@ -40,6 +42,7 @@ pub fn clear_blocks<F: FnMut(&syn::File) -> Result<(),String>>(file: &mut syn::F
             break
         }
 
+        cursor.set(&[visitor.target_index]);
         if let Ok(()) = try_compile(file) {
             // this change works, keep it!
             visitor.backup = None;
@@ -62,7 +65,7 @@ impl VisitMut for BlockVisitor {
 			if let Some(backup) = self.backup.take() {
 				// the change we tried didn't work. revert and try the next
 				// possible change
-				mem::replace(i, backup);
+				*i = backup;
 			} else if *i != self.unimplemented {
 				self.backup = Some(mem::replace(i, self.unimplemented.clone()));
 				return;
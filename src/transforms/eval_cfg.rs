@@ -0,0 +1,500 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Resolve `#[cfg(...)]` and `#[cfg_attr(...)]` for a given configuration:
+/// remove everything that's configured out, expand `cfg_attr`s that apply
+/// and drop the rest, along with `cfg`s that are always true.
+use std::collections::BTreeSet;
+use std::io;
+use std::process::Command;
+use std::result::Result;
+use proc_macro2::{TokenStream, TokenTree};
+use syn::{*, parse::{Parse, ParseStream}, punctuated::{Pair, Punctuated}, visit_mut::*};
+
+use crate::checkpoint::Cursor;
+use super::{one_at_a_time, try_changes};
+
+/// The names and `name = "value"` pairs that are set, like `rustc --cfg`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CfgSet {
+    cfgs: BTreeSet<(String, Option<String>)>,
+}
+
+impl CfgSet {
+    /// What `rustc` sets for `target`, or for the host.
+    pub fn from_rustc(target: Option<&str>) -> io::Result<CfgSet> {
+        let mut command = Command::new("rustc");
+        command.args(["--print", "cfg"]);
+        if let Some(target) = target {
+            command.args(["--target", target]);
+        }
+        let out = command.output()?;
+        if !out.status.success() {
+            // most likely an unknown target
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, String::from_utf8_lossy(&out.stderr).into_owned()));
+        }
+        let mut set = CfgSet::default();
+        for line in String::from_utf8_lossy(&out.stdout).lines() {
+            set.insert_spec(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        Ok(set)
+    }
+
+    /// What `rustc` sets for `target`, plus `--cfg`s given as `cfgs` and
+    /// comma-separated `features`, as taken on the command line.
+    pub fn from_args<'a, I: IntoIterator<Item = &'a str>>(target: Option<&str>, cfgs: I, features: Option<&str>) -> Result<CfgSet, String> {
+        let mut set = CfgSet::from_rustc(target).map_err(|e| format!("couldn't get the cfgs from rustc: {}", e))?;
+        for spec in cfgs {
+            set.insert_spec(spec)?;
+        }
+        for feature in features.into_iter().flat_map(|features| features.split(',')) {
+            set.insert("feature", Some(feature.trim()));
+        }
+        Ok(set)
+    }
+
+    pub fn insert(&mut self, name: &str, value: Option<&str>) {
+        self.cfgs.insert((name.to_owned(), value.map(ToOwned::to_owned)));
+    }
+
+    /// Add `name` or `name="value"`, as given to `rustc --cfg`.
+    pub fn insert_spec(&mut self, spec: &str) -> Result<(), String> {
+        let (name, value) = match spec.find('=') {
+            Some(eq) => {
+                let value = spec[eq + 1..].trim();
+                let value = value.strip_prefix('"').and_then(|value| value.strip_suffix('"'))
+                    .ok_or_else(|| format!("invalid cfg `{}`, the value must be quoted", spec))?;
+                (spec[..eq].trim(), Some(value))
+            },
+            None => (spec.trim(), None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("invalid cfg `{}`", spec));
+        }
+        self.insert(name, value);
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str, value: Option<&str>) -> bool {
+        self.cfgs.contains(&(name.to_owned(), value.map(ToOwned::to_owned)))
+    }
+
+    /// Whether a `cfg` predicate holds. `None` if it isn't understood.
+    pub fn eval(&self, meta: &NestedMeta) -> Option<bool> {
+        let meta = match meta {
+            NestedMeta::Meta(meta) => meta,
+            NestedMeta::Literal(_) => return None,
+        };
+        match meta {
+            Meta::Word(name) => Some(self.contains(&name.to_string(), None)),
+            Meta::NameValue(MetaNameValue { ident, lit: Lit::Str(value), .. }) => {
+                Some(self.contains(&ident.to_string(), Some(&value.value())))
+            },
+            Meta::NameValue(_) => None,
+            Meta::List(list) => {
+                let mut values = list.nested.iter().map(|meta| self.eval(meta));
+                if list.ident == "all" {
+                    values.try_fold(true, |all, value| Some(all && value?))
+                } else if list.ident == "any" {
+                    values.try_fold(false, |any, value| Some(any || value?))
+                } else if list.ident == "not" && list.nested.len() == 1 {
+                    values.next().expect("one").map(|value| !value)
+                } else {
+                    None
+                }
+            },
+        }
+    }
+}
+
+/// Resolves everything at once, and if that isn't interesting, each
+/// `cfg_attr` and then each `cfg` on its own, so one that's needed doesn't
+/// stop the others from going.
+pub fn eval_cfg<F: FnMut(&File) -> Result<(),String>>(file: &mut File, cursor: &Cursor, cfg: &CfgSet, mut try_compile: F) {
+    let [mut phase, mut target_index] = cursor.start([0, 1]);
+
+    if phase == 0 {
+        let mut candidate = file.clone();
+        CfgAttrVisitor { cfg, target: Target::all() }.visit_file_mut(&mut candidate);
+        CfgVisitor { cfg, target: Target::all() }.visit_file_mut(&mut candidate);
+        if candidate == *file {
+            return;
+        }
+        cursor.set(&[0, 1]);
+        if let Ok(()) = try_compile(&candidate) {
+            *file = candidate;
+            return;
+        }
+        phase = 1;
+    }
+
+    // `cfg_attr`s first, as they can expand into `cfg`s
+    let mut try_batch = one_at_a_time(&mut try_compile);
+    while phase <= 2 {
+        let change = |file: &mut File, target_index| {
+            let target = Target::one(target_index);
+            if phase == 1 {
+                let mut visitor = CfgAttrVisitor { cfg, target };
+                visitor.visit_file_mut(file);
+                visitor.target.changed
+            } else {
+                let mut visitor = CfgVisitor { cfg, target };
+                visitor.visit_file_mut(file);
+                visitor.target.changed
+            }
+        };
+        try_changes(file, cursor, &[phase], target_index, 1, change, &mut try_batch);
+
+        phase += 1;
+        target_index = 1;
+    }
+}
+
+/// Which of the `cfg`s or `cfg_attr`s a visitor comes across it should
+/// resolve: all of them, or just the `target_index`th.
+struct Target {
+	target_index: Option<usize>,
+	cur_index: usize,
+	changed: bool,
+}
+
+impl Target {
+	fn all() -> Target {
+		Target { target_index: None, cur_index: 0, changed: false }
+	}
+
+	fn one(target_index: usize) -> Target {
+		Target { target_index: Some(target_index), cur_index: 0, changed: false }
+	}
+
+	/// Called for each one that could be resolved, in order. Returns whether
+	/// to resolve it.
+	fn next(&mut self) -> bool {
+		if self.changed && self.target_index.is_some() {
+			return false;
+		}
+		self.cur_index += 1;
+		let hit = match self.target_index {
+			Some(target_index) => target_index == self.cur_index,
+			None => true,
+		};
+		self.changed |= hit;
+		hit
+	}
+}
+
+/// `#[cfg_attr(predicate, attr, ...)]`, split up.
+struct CfgAttr {
+	predicate: NestedMeta,
+	attrs: Vec<(Path, TokenStream)>,
+}
+
+impl Parse for CfgAttr {
+	fn parse(input: ParseStream) -> syn::parse::Result<Self> {
+		let content;
+		parenthesized!(content in input);
+		let predicate = content.parse()?;
+		let mut attrs = Vec::new();
+		while !content.is_empty() {
+			content.parse::<Token![,]>()?;
+			if content.is_empty() {
+				break;
+			}
+			let path = content.call(Path::parse_mod_style)?;
+			let mut tts = TokenStream::new();
+			while !content.is_empty() && !content.peek(Token![,]) {
+				tts.extend(Some(content.parse::<TokenTree>()?));
+			}
+			attrs.push((path, tts));
+		}
+		Ok(CfgAttr { predicate, attrs })
+	}
+}
+
+/// Expands `cfg_attr`s everywhere, so there are only `cfg`s left to look at.
+struct CfgAttrVisitor<'a> {
+	cfg: &'a CfgSet,
+	target: Target,
+}
+
+impl CfgAttrVisitor<'_> {
+	fn visit_attr_container(&mut self, attrs: &mut Vec<Attribute>) {
+		let mut expanded = Vec::with_capacity(attrs.len());
+		for attr in attrs.drain(..) {
+			let cfg_attr = if attr.path.is_ident("cfg_attr") {
+				syn::parse2::<CfgAttr>(attr.tts.clone()).ok()
+			} else {
+				None
+			};
+			let cfg_attr = cfg_attr.and_then(|cfg_attr| Some((self.cfg.eval(&cfg_attr.predicate)?, cfg_attr.attrs)));
+			match cfg_attr {
+				Some((true, attrs)) if self.target.next() => expanded.extend(attrs.into_iter().map(|(path, tts)| {
+					Attribute { path, tts, ..attr.clone() }
+				})),
+				Some((false, _)) if self.target.next() => {},
+				_ => expanded.push(attr),
+			}
+		}
+		*attrs = expanded;
+	}
+}
+
+impl_VisitMut_attrs!(CfgAttrVisitor<'_>);
+
+/// Removes everything whose `cfg` is false, and `cfg`s that are true.
+struct CfgVisitor<'a> {
+	cfg: &'a CfgSet,
+	target: Target,
+}
+
+impl CfgVisitor<'_> {
+	/// What a `#[cfg]` evaluates to, if `attr` is one and it's understood.
+	fn eval(&self, attr: &Attribute) -> Option<bool> {
+		if !attr.path.is_ident("cfg") {
+			return None;
+		}
+		match attr.parse_meta() {
+			Ok(Meta::List(ref list)) if list.nested.len() == 1 => self.cfg.eval(&list.nested[0]),
+			_ => None,
+		}
+	}
+
+	/// Whether something with these attributes is configured in. If so, the
+	/// `cfg`s that made sure of that are removed. Nodes the target doesn't
+	/// pick are left as they are.
+	fn enabled(&mut self, attrs: &mut Vec<Attribute>) -> bool {
+		if !attrs.iter().any(|attr| self.eval(attr).is_some()) || !self.target.next() {
+			return true;
+		}
+		let mut enabled = true;
+		attrs.retain(|attr| match self.eval(attr) {
+			Some(true) => false,
+			Some(false) => {
+				enabled = false;
+				true
+			},
+			None => true,
+		});
+		enabled
+	}
+
+	fn retain<T, F: FnMut(&mut T) -> Option<&mut Vec<Attribute>>>(&mut self, nodes: &mut Vec<T>, mut attrs: F) {
+		nodes.retain_mut(|node| match attrs(node) {
+			Some(attrs) => self.enabled(attrs),
+			None => true,
+		});
+	}
+
+	fn retain_punctuated<T, P: Default, F: FnMut(&mut T) -> Option<&mut Vec<Attribute>>>(&mut self, nodes: &mut Punctuated<T, P>, mut attrs: F) {
+		// by pairs, so a trailing comma, as in `(a,)`, stays
+		let old = std::mem::take(nodes);
+		*nodes = old.into_pairs().filter_map(|pair| {
+			let (mut node, punct) = pair.into_tuple();
+			let enabled = match attrs(&mut node) {
+				Some(attrs) => self.enabled(attrs),
+				None => true,
+			};
+			if enabled { Some(Pair::new(node, punct)) } else { None }
+		}).collect();
+	}
+}
+
+fn item_attrs(item: &mut Item) -> Option<&mut Vec<Attribute>> {
+	Some(match item {
+		Item::ExternCrate(i) => &mut i.attrs,
+		Item::Use(i) => &mut i.attrs,
+		Item::Static(i) => &mut i.attrs,
+		Item::Const(i) => &mut i.attrs,
+		Item::Fn(i) => &mut i.attrs,
+		Item::Mod(i) => &mut i.attrs,
+		Item::ForeignMod(i) => &mut i.attrs,
+		Item::Type(i) => &mut i.attrs,
+		Item::Existential(i) => &mut i.attrs,
+		Item::Struct(i) => &mut i.attrs,
+		Item::Enum(i) => &mut i.attrs,
+		Item::Union(i) => &mut i.attrs,
+		Item::Trait(i) => &mut i.attrs,
+		Item::TraitAlias(i) => &mut i.attrs,
+		Item::Impl(i) => &mut i.attrs,
+		Item::Macro(i) => &mut i.attrs,
+		Item::Macro2(i) => &mut i.attrs,
+		Item::Verbatim(_) => return None,
+	})
+}
+
+fn impl_item_attrs(item: &mut ImplItem) -> Option<&mut Vec<Attribute>> {
+	Some(match item {
+		ImplItem::Const(i) => &mut i.attrs,
+		ImplItem::Method(i) => &mut i.attrs,
+		ImplItem::Type(i) => &mut i.attrs,
+		ImplItem::Existential(i) => &mut i.attrs,
+		ImplItem::Macro(i) => &mut i.attrs,
+		ImplItem::Verbatim(_) => return None,
+	})
+}
+
+fn trait_item_attrs(item: &mut TraitItem) -> Option<&mut Vec<Attribute>> {
+	Some(match item {
+		TraitItem::Const(i) => &mut i.attrs,
+		TraitItem::Method(i) => &mut i.attrs,
+		TraitItem::Type(i) => &mut i.attrs,
+		TraitItem::Macro(i) => &mut i.attrs,
+		TraitItem::Verbatim(_) => return None,
+	})
+}
+
+fn foreign_item_attrs(item: &mut ForeignItem) -> Option<&mut Vec<Attribute>> {
+	Some(match item {
+		ForeignItem::Static(i) => &mut i.attrs,
+		ForeignItem::Fn(i) => &mut i.attrs,
+		ForeignItem::Type(i) => &mut i.attrs,
+		ForeignItem::Macro(i) => &mut i.attrs,
+		ForeignItem::Verbatim(_) => return None,
+	})
+}
+
+fn stmt_attrs(stmt: &mut Stmt) -> Option<&mut Vec<Attribute>> {
+	match stmt {
+		Stmt::Local(local) => Some(&mut local.attrs),
+		Stmt::Item(item) => item_attrs(item),
+		Stmt::Expr(expr) | Stmt::Semi(expr, _) => expr_attrs(expr),
+	}
+}
+
+fn expr_attrs(expr: &mut Expr) -> Option<&mut Vec<Attribute>> {
+	Some(match expr {
+		Expr::Box(i) => &mut i.attrs,
+		Expr::InPlace(i) => &mut i.attrs,
+		Expr::Array(i) => &mut i.attrs,
+		Expr::Call(i) => &mut i.attrs,
+		Expr::MethodCall(i) => &mut i.attrs,
+		Expr::Tuple(i) => &mut i.attrs,
+		Expr::Binary(i) => &mut i.attrs,
+		Expr::Unary(i) => &mut i.attrs,
+		Expr::Lit(i) => &mut i.attrs,
+		Expr::Cast(i) => &mut i.attrs,
+		Expr::Type(i) => &mut i.attrs,
+		Expr::Let(i) => &mut i.attrs,
+		Expr::If(i) => &mut i.attrs,
+		Expr::While(i) => &mut i.attrs,
+		Expr::ForLoop(i) => &mut i.attrs,
+		Expr::Loop(i) => &mut i.attrs,
+		Expr::Match(i) => &mut i.attrs,
+		Expr::Closure(i) => &mut i.attrs,
+		Expr::Unsafe(i) => &mut i.attrs,
+		Expr::Block(i) => &mut i.attrs,
+		Expr::Assign(i) => &mut i.attrs,
+		Expr::AssignOp(i) => &mut i.attrs,
+		Expr::Field(i) => &mut i.attrs,
+		Expr::Index(i) => &mut i.attrs,
+		Expr::Range(i) => &mut i.attrs,
+		Expr::Path(i) => &mut i.attrs,
+		Expr::Reference(i) => &mut i.attrs,
+		Expr::Break(i) => &mut i.attrs,
+		Expr::Continue(i) => &mut i.attrs,
+		Expr::Return(i) => &mut i.attrs,
+		Expr::Macro(i) => &mut i.attrs,
+		Expr::Struct(i) => &mut i.attrs,
+		Expr::Repeat(i) => &mut i.attrs,
+		Expr::Paren(i) => &mut i.attrs,
+		Expr::Group(i) => &mut i.attrs,
+		Expr::Try(i) => &mut i.attrs,
+		Expr::Async(i) => &mut i.attrs,
+		Expr::TryBlock(i) => &mut i.attrs,
+		Expr::Yield(i) => &mut i.attrs,
+		Expr::Verbatim(_) => return None,
+	})
+}
+
+impl VisitMut for CfgVisitor<'_> {
+	fn visit_file_mut(&mut self, i: &mut File) {
+		self.retain(&mut i.items, item_attrs);
+		visit_file_mut(self, i);
+	}
+
+	fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
+		if let Some((_, items)) = &mut i.content {
+			self.retain(items, item_attrs);
+		}
+		visit_item_mod_mut(self, i);
+	}
+
+	fn visit_item_impl_mut(&mut self, i: &mut ItemImpl) {
+		self.retain(&mut i.items, impl_item_attrs);
+		visit_item_impl_mut(self, i);
+	}
+
+	fn visit_item_trait_mut(&mut self, i: &mut ItemTrait) {
+		self.retain(&mut i.items, trait_item_attrs);
+		visit_item_trait_mut(self, i);
+	}
+
+	fn visit_item_foreign_mod_mut(&mut self, i: &mut ItemForeignMod) {
+		self.retain(&mut i.items, foreign_item_attrs);
+		visit_item_foreign_mod_mut(self, i);
+	}
+
+	fn visit_block_mut(&mut self, i: &mut Block) {
+		self.retain(&mut i.stmts, stmt_attrs);
+		visit_block_mut(self, i);
+	}
+
+	fn visit_expr_match_mut(&mut self, i: &mut ExprMatch) {
+		self.retain(&mut i.arms, |arm| Some(&mut arm.attrs));
+		visit_expr_match_mut(self, i);
+	}
+
+	fn visit_expr_array_mut(&mut self, i: &mut ExprArray) {
+		self.retain_punctuated(&mut i.elems, expr_attrs);
+		visit_expr_array_mut(self, i);
+	}
+
+	fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
+		self.retain_punctuated(&mut i.args, expr_attrs);
+		visit_expr_call_mut(self, i);
+	}
+
+	fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
+		self.retain_punctuated(&mut i.args, expr_attrs);
+		visit_expr_method_call_mut(self, i);
+	}
+
+	fn visit_expr_tuple_mut(&mut self, i: &mut ExprTuple) {
+		self.retain_punctuated(&mut i.elems, expr_attrs);
+		visit_expr_tuple_mut(self, i);
+	}
+
+	fn visit_item_enum_mut(&mut self, i: &mut ItemEnum) {
+		self.retain_punctuated(&mut i.variants, |variant| Some(&mut variant.attrs));
+		visit_item_enum_mut(self, i);
+	}
+
+	fn visit_fields_named_mut(&mut self, i: &mut FieldsNamed) {
+		self.retain_punctuated(&mut i.named, |field| Some(&mut field.attrs));
+		visit_fields_named_mut(self, i);
+	}
+
+	fn visit_fields_unnamed_mut(&mut self, i: &mut FieldsUnnamed) {
+		self.retain_punctuated(&mut i.unnamed, |field| Some(&mut field.attrs));
+		visit_fields_unnamed_mut(self, i);
+	}
+
+	fn visit_expr_struct_mut(&mut self, i: &mut ExprStruct) {
+		self.retain_punctuated(&mut i.fields, |field| Some(&mut field.attrs));
+		visit_expr_struct_mut(self, i);
+	}
+}
@@ -15,9 +15,486 @@
 // You should have received a copy of the GNU General Public License
 // along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
 
+//! The built-in passes.
+
+/// Implement `VisitMut` for `$visitor`, calling its
+/// `visit_attr_container(&mut self, &mut Vec<Attribute>)` on the attributes
+/// of every node that has them, before visiting the node itself.
+macro_rules! impl_VisitMut_attrs {
+	(@fns $(fn $i:ident(&mut self, i: &mut $t:ident))*) => {
+		$(
+			fn $i(&mut self, i: &mut syn::$t) {
+				self.visit_attr_container(&mut i.attrs);
+				syn::visit_mut::$i(self, i);
+			}
+		)*
+	};
+	($visitor:ty) => {
+		impl syn::visit_mut::VisitMut for $visitor {
+			impl_VisitMut_attrs! { @fns
+				fn visit_arm_mut(&mut self, i: &mut Arm)
+				fn visit_const_param_mut(&mut self, i: &mut ConstParam)
+				fn visit_derive_input_mut(&mut self, i: &mut DeriveInput)
+				fn visit_expr_array_mut(&mut self, i: &mut ExprArray)
+				fn visit_expr_assign_mut(&mut self, i: &mut ExprAssign)
+				fn visit_expr_assign_op_mut(&mut self, i: &mut ExprAssignOp)
+				fn visit_expr_async_mut(&mut self, i: &mut ExprAsync)
+				fn visit_expr_binary_mut(&mut self, i: &mut ExprBinary)
+				fn visit_expr_block_mut(&mut self, i: &mut ExprBlock)
+				fn visit_expr_box_mut(&mut self, i: &mut ExprBox)
+				fn visit_expr_break_mut(&mut self, i: &mut ExprBreak)
+				fn visit_expr_call_mut(&mut self, i: &mut ExprCall)
+				fn visit_expr_cast_mut(&mut self, i: &mut ExprCast)
+				fn visit_expr_closure_mut(&mut self, i: &mut ExprClosure)
+				fn visit_expr_continue_mut(&mut self, i: &mut ExprContinue)
+				fn visit_expr_field_mut(&mut self, i: &mut ExprField)
+				fn visit_expr_for_loop_mut(&mut self, i: &mut ExprForLoop)
+				fn visit_expr_group_mut(&mut self, i: &mut ExprGroup)
+				fn visit_expr_if_mut(&mut self, i: &mut ExprIf)
+				fn visit_expr_in_place_mut(&mut self, i: &mut ExprInPlace)
+				fn visit_expr_index_mut(&mut self, i: &mut ExprIndex)
+				fn visit_expr_let_mut(&mut self, i: &mut ExprLet)
+				fn visit_expr_lit_mut(&mut self, i: &mut ExprLit)
+				fn visit_expr_loop_mut(&mut self, i: &mut ExprLoop)
+				fn visit_expr_macro_mut(&mut self, i: &mut ExprMacro)
+				fn visit_expr_match_mut(&mut self, i: &mut ExprMatch)
+				fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall)
+				fn visit_expr_paren_mut(&mut self, i: &mut ExprParen)
+				fn visit_expr_path_mut(&mut self, i: &mut ExprPath)
+				fn visit_expr_range_mut(&mut self, i: &mut ExprRange)
+				fn visit_expr_reference_mut(&mut self, i: &mut ExprReference)
+				fn visit_expr_repeat_mut(&mut self, i: &mut ExprRepeat)
+				fn visit_expr_return_mut(&mut self, i: &mut ExprReturn)
+				fn visit_expr_struct_mut(&mut self, i: &mut ExprStruct)
+				fn visit_expr_try_mut(&mut self, i: &mut ExprTry)
+				fn visit_expr_try_block_mut(&mut self, i: &mut ExprTryBlock)
+				fn visit_expr_tuple_mut(&mut self, i: &mut ExprTuple)
+				fn visit_expr_type_mut(&mut self, i: &mut ExprType)
+				fn visit_expr_unary_mut(&mut self, i: &mut ExprUnary)
+				fn visit_expr_unsafe_mut(&mut self, i: &mut ExprUnsafe)
+				fn visit_expr_while_mut(&mut self, i: &mut ExprWhile)
+				fn visit_expr_yield_mut(&mut self, i: &mut ExprYield)
+				fn visit_field_mut(&mut self, i: &mut Field)
+				fn visit_field_pat_mut(&mut self, i: &mut FieldPat)
+				fn visit_field_value_mut(&mut self, i: &mut FieldValue)
+				fn visit_file_mut(&mut self, i: &mut File)
+				fn visit_foreign_item_fn_mut(&mut self, i: &mut ForeignItemFn)
+				fn visit_foreign_item_macro_mut(&mut self, i: &mut ForeignItemMacro)
+				fn visit_foreign_item_static_mut(&mut self, i: &mut ForeignItemStatic)
+				fn visit_foreign_item_type_mut(&mut self, i: &mut ForeignItemType)
+				fn visit_impl_item_const_mut(&mut self, i: &mut ImplItemConst)
+				fn visit_impl_item_existential_mut(&mut self, i: &mut ImplItemExistential)
+				fn visit_impl_item_macro_mut(&mut self, i: &mut ImplItemMacro)
+				fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod)
+				fn visit_impl_item_type_mut(&mut self, i: &mut ImplItemType)
+				fn visit_item_const_mut(&mut self, i: &mut ItemConst)
+				fn visit_item_enum_mut(&mut self, i: &mut ItemEnum)
+				fn visit_item_existential_mut(&mut self, i: &mut ItemExistential)
+				fn visit_item_extern_crate_mut(&mut self, i: &mut ItemExternCrate)
+				fn visit_item_fn_mut(&mut self, i: &mut ItemFn)
+				fn visit_item_foreign_mod_mut(&mut self, i: &mut ItemForeignMod)
+				fn visit_item_impl_mut(&mut self, i: &mut ItemImpl)
+				fn visit_item_macro_mut(&mut self, i: &mut ItemMacro)
+				fn visit_item_macro2_mut(&mut self, i: &mut ItemMacro2)
+				fn visit_item_mod_mut(&mut self, i: &mut ItemMod)
+				fn visit_item_static_mut(&mut self, i: &mut ItemStatic)
+				fn visit_item_struct_mut(&mut self, i: &mut ItemStruct)
+				fn visit_item_trait_mut(&mut self, i: &mut ItemTrait)
+				fn visit_item_trait_alias_mut(&mut self, i: &mut ItemTraitAlias)
+				fn visit_item_type_mut(&mut self, i: &mut ItemType)
+				fn visit_item_union_mut(&mut self, i: &mut ItemUnion)
+				fn visit_item_use_mut(&mut self, i: &mut ItemUse)
+				fn visit_lifetime_def_mut(&mut self, i: &mut LifetimeDef)
+				fn visit_local_mut(&mut self, i: &mut Local)
+				fn visit_trait_item_const_mut(&mut self, i: &mut TraitItemConst)
+				fn visit_trait_item_macro_mut(&mut self, i: &mut TraitItemMacro)
+				fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod)
+				fn visit_trait_item_type_mut(&mut self, i: &mut TraitItemType)
+				fn visit_type_param_mut(&mut self, i: &mut TypeParam)
+				fn visit_variant_mut(&mut self, i: &mut Variant)
+			}
+		}
+	};
+}
+
+pub mod eval_cfg;
 pub mod prune_items;
+pub mod remove_attrs;
 pub mod remove_derive_attrs;
+pub mod remove_derive_traits;
 pub mod remove_doc_attrs;
 pub mod empty_blocks;
 pub mod clear_blocks;
-pub mod privatiser;
\ No newline at end of file
+pub mod reduce_generics;
+pub mod reduce_signatures;
+pub mod remove_default_bodies;
+pub mod remove_stmts;
+pub mod simplify_exprs;
+pub mod privatiser;
+
+use crate::Options;
+use crate::checkpoint::Cursor;
+use crate::pass::{Pass, Tester};
+
+/// Everything, in the order `Reducer` runs it by default, set up according
+/// to `options`.
+pub fn default_passes(options: &Options) -> Vec<Box<dyn Pass>> {
+    let mut keep_attrs = options.keep_attrs.clone();
+    if options.split_modules {
+        // Needed to tell which file a module goes back into.
+        keep_attrs.push("path".to_owned());
+    }
+    vec![
+        Box::new(EvalCfg { cfg: options.cfg.clone() }),
+        Box::new(PruneItems { ddmin: options.ddmin }),
+        Box::new(RemoveDeriveAttrs),
+        Box::new(RemoveDeriveTraits),
+        Box::new(RemoveDocAttrs),
+        Box::new(RemoveAttrs { keep: keep_attrs, only: options.only_attrs.clone() }),
+        Box::new(EmptyBlocks),
+        Box::new(ClearBlocks),
+        Box::new(RemoveDefaultBodies),
+        Box::new(RemoveStmts),
+        Box::new(SimplifyExprs),
+        Box::new(ReduceSignatures),
+        Box::new(ReduceGenerics),
+        Box::new(PrivatiseItems),
+    ]
+}
+
+/// The passes called `names`, in that order, or all of them in the default
+/// order if `names` is empty. Then leave out the ones called `skip`.
+pub fn select_passes(names: &[&str], skip: &[&str], options: &Options) -> Result<Vec<Box<dyn Pass>>, String> {
+    let mut available = default_passes(options);
+    for name in names.iter().chain(skip) {
+        if !available.iter().any(|pass| pass.name() == *name) {
+            let known: Vec<_> = available.iter().map(|pass| pass.name()).collect();
+            return Err(format!("unknown pass `{}`, expected one of {}", name, known.join(", ")));
+        }
+    }
+
+    let mut passes = Vec::new();
+    if names.is_empty() {
+        passes = available;
+    } else {
+        for name in names {
+            match available.iter().position(|pass| pass.name() == *name) {
+                Some(index) => passes.push(available.remove(index)),
+                None => return Err(format!("pass `{}` given more than once", name)),
+            }
+        }
+    }
+    passes.retain(|pass| !skip.contains(&pass.name()));
+    Ok(passes)
+}
+
+/// See `prune_items::prune_items`. With `ddmin`, see
+/// `prune_items::prune_items_ddmin`.
+pub struct PruneItems {
+    pub ddmin: bool,
+}
+
+impl Pass for PruneItems {
+    fn name(&self) -> &str {
+        "prune"
+    }
+
+    fn description(&self) -> String {
+        if self.ddmin { "Pruning items (ddmin)" } else { "Pruning items" }.to_owned()
+    }
+
+    fn run(&mut self, file: &mut syn::File, tester: &mut Tester) {
+        let cursor = tester.cursor();
+        if tester.jobs() > 1 {
+            let jobs = tester.jobs();
+            prune_items::prune_items_parallel(file, cursor, self.ddmin, jobs, |candidates| tester.test_batch(candidates));
+        } else if self.ddmin {
+            prune_items::prune_items_ddmin(file, cursor, |file| tester.test(file));
+        } else {
+            prune_items::prune_items(file, cursor, |file| tester.test(file));
+        }
+    }
+}
+
+/// See `eval_cfg::eval_cfg`. Does nothing without a `cfg`.
+pub struct EvalCfg {
+    pub cfg: Option<eval_cfg::CfgSet>,
+}
+
+impl Pass for EvalCfg {
+    fn name(&self) -> &str {
+        "eval-cfg"
+    }
+
+    fn description(&self) -> String {
+        "Evaluating #[cfg] attributes".to_owned()
+    }
+
+    fn run(&mut self, file: &mut syn::File, tester: &mut Tester) {
+        if let Some(cfg) = &self.cfg {
+            eval_cfg::eval_cfg(file, tester.cursor(), cfg, |file| tester.test(file));
+        }
+    }
+}
+
+/// See `remove_attrs::remove_attrs`.
+pub struct RemoveAttrs {
+    /// Paths of attributes never to remove.
+    pub keep: Vec<String>,
+    /// If not empty, the paths of the only attributes to try removing.
+    pub only: Vec<String>,
+}
+
+impl Pass for RemoveAttrs {
+    fn name(&self) -> &str {
+        "remove-attrs"
+    }
+
+    fn description(&self) -> String {
+        "Removing attributes".to_owned()
+    }
+
+    fn run(&mut self, file: &mut syn::File, tester: &mut Tester) {
+        let cursor = tester.cursor();
+        let jobs = tester.jobs();
+        remove_attrs::remove_attrs_parallel(file, cursor, &self.keep, &self.only, jobs, |candidates| tester.test_batch(candidates));
+    }
+}
+
+macro_rules! passes {
+    ($($(#[$attr:meta])* $pass:ident: $name:literal, $description:literal, $run:path;)*) => {
+        $(
+            $(#[$attr])*
+            pub struct $pass;
+
+            impl Pass for $pass {
+                fn name(&self) -> &str {
+                    $name
+                }
+
+                fn description(&self) -> String {
+                    $description.to_owned()
+                }
+
+                fn run(&mut self, file: &mut syn::File, tester: &mut Tester) {
+                    let cursor = tester.cursor();
+                    $run(file, cursor, |file: &syn::File| tester.test(file))
+                }
+            }
+        )*
+    }
+}
+
+passes! {
+    /// See `remove_derive_attrs::remove_derive_attrs`.
+    RemoveDeriveAttrs: "remove-derive", "Removing #[derive] attributes", remove_derive_attrs::remove_derive_attrs;
+    /// See `remove_derive_traits::remove_derive_traits`.
+    RemoveDeriveTraits: "remove-derive-traits", "Removing traits from #[derive] attributes", remove_derive_traits::remove_derive_traits;
+    /// See `remove_doc_attrs::remove_doc_attrs`.
+    RemoveDocAttrs: "remove-doc", "Removing #[doc] attributes", remove_doc_attrs::remove_doc_attrs;
+    /// See `empty_blocks::empty_blocks`.
+    EmptyBlocks: "empty-blocks", "Clearing block bodies - {}", empty_blocks::empty_blocks;
+    /// See `clear_blocks::clear_blocks`.
+    ClearBlocks: "clear-blocks", "Clearing block bodies - unimplemented", clear_blocks::clear_blocks;
+    /// See `remove_default_bodies::remove_default_bodies`.
+    RemoveDefaultBodies: "remove-default-bodies", "Removing provided trait method bodies", remove_default_bodies::remove_default_bodies;
+    /// See `privatiser::privatise_items`.
+    PrivatiseItems: "privatise", "Removing pub", privatiser::privatise_items;
+}
+
+/// Like `passes!`, but for passes that can hand several candidates at a time
+/// to `Tester::test_batch`.
+macro_rules! batched_passes {
+    ($($(#[$attr:meta])* $pass:ident: $name:literal, $description:literal, $run:path;)*) => {
+        $(
+            $(#[$attr])*
+            pub struct $pass;
+
+            impl Pass for $pass {
+                fn name(&self) -> &str {
+                    $name
+                }
+
+                fn description(&self) -> String {
+                    $description.to_owned()
+                }
+
+                fn run(&mut self, file: &mut syn::File, tester: &mut Tester) {
+                    let cursor = tester.cursor();
+                    let jobs = tester.jobs();
+                    $run(file, cursor, jobs, |candidates: &[syn::File]| tester.test_batch(candidates))
+                }
+            }
+        )*
+    }
+}
+
+batched_passes! {
+    /// See `remove_stmts::remove_stmts`.
+    RemoveStmts: "remove-stmts", "Removing statements", remove_stmts::remove_stmts_parallel;
+    /// See `simplify_exprs::simplify_exprs`.
+    SimplifyExprs: "simplify-exprs", "Simplifying expressions", simplify_exprs::simplify_exprs_parallel;
+    /// See `reduce_signatures::reduce_signatures`.
+    ReduceSignatures: "reduce-signatures", "Removing function parameters and return types", reduce_signatures::reduce_signatures_parallel;
+    /// See `reduce_generics::reduce_generics`.
+    ReduceGenerics: "reduce-generics", "Removing generic parameters and bounds", reduce_generics::reduce_generics_parallel;
+}
+
+/// Turns a `try_compile` into a `try_batch` for batches of one.
+pub(crate) fn one_at_a_time<F: FnMut(&syn::File) -> Result<(),String>>(mut try_compile: F) -> impl FnMut(&[syn::File]) -> Option<usize> {
+    move |candidates| {
+        match try_compile(&candidates[0]) {
+            Ok(()) => Some(0),
+            Err(_msg) => None,
+        }
+    }
+}
+
+/// Tries the changes a pass can make in turn, handing up to `jobs`
+/// candidates at a time to `try_batch`.
+///
+/// `try_batch` is what the batched passes take: given the candidates, it
+/// returns the index of the first interesting one, if any, which is then
+/// kept. `one_at_a_time` makes one out of a `try_compile`.
+///
+/// `change(file, index)` makes the `index`th change, counting from 1, and
+/// returns whether there was one. Once a change is kept, the same index
+/// refers to the next one. The cursor is `prefix` followed by the index.
+pub(crate) fn try_changes<C, F>(file: &mut syn::File, cursor: &Cursor, prefix: &[usize], mut index: usize, jobs: usize, mut change: C, mut try_batch: F)
+    where C: FnMut(&mut syn::File, usize) -> bool, F: FnMut(&[syn::File]) -> Option<usize>
+{
+    loop {
+        let mut candidates = Vec::with_capacity(jobs);
+        while candidates.len() < jobs {
+            let mut candidate = file.clone();
+            if !change(&mut candidate, index + candidates.len()) {
+                break;
+            }
+            candidates.push(candidate);
+        }
+
+        // no more changes to be made
+        if candidates.is_empty() {
+            break
+        }
+
+        cursor.set(&prefix.iter().copied().chain(Some(index)).collect::<Vec<_>>());
+        if let Some(winner) = try_batch(&candidates) {
+            // this change works, keep it!
+            *file = candidates.swap_remove(winner);
+            index += winner;
+        } else {
+            index += candidates.len();
+        }
+    }
+}
+
+/// Something that can be referred to by a path, e.g. a struct or function,
+/// for passes that have to change where it's used as well.
+pub(crate) struct Owner {
+    pub(crate) name: syn::Ident,
+    /// The modules it's in, from the crate root.
+    pub(crate) module: Vec<syn::Ident>,
+    /// For methods and associated functions, the name of the type or trait
+    /// they're in.
+    pub(crate) parent: Option<syn::Ident>,
+}
+
+impl Owner {
+    /// Whether a path used in `module` that goes through `prefix` before the
+    /// owner's name leads to it. Associated items have to be reached through
+    /// `Self` or the name of their type or trait. Otherwise, the path has to
+    /// lead to the owner's module, and `use`s aren't followed.
+    pub(crate) fn reached_by(&self, module: &[syn::Ident], leading_colon: bool, prefix: &[&syn::Ident]) -> bool {
+        if let Some(parent) = &self.parent {
+            return prefix.last().is_some_and(|last| *last == "Self" || *last == parent);
+        }
+        if leading_colon {
+            return false;
+        }
+
+        let mut module = module.to_vec();
+        let mut rest = prefix;
+        match prefix.first() {
+            Some(first) if *first == "crate" => {
+                module.clear();
+                rest = &prefix[1..];
+            },
+            Some(first) if *first == "self" => rest = &prefix[1..],
+            _ => {},
+        }
+        while let Some((first, tail)) = rest.split_first() {
+            if *first != "super" || module.pop().is_none() {
+                break;
+            }
+            rest = tail;
+        }
+        module.extend(rest.iter().map(|ident| (*ident).clone()));
+        module == self.module
+    }
+}
+
+/// The name of the type an impl is for, if it's a plain path.
+pub(crate) fn self_ty_name(ty: &syn::Type) -> Option<syn::Ident> {
+    match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => path.segments.iter().last().map(|segment| segment.ident.clone()),
+        _ => None,
+    }
+}
+
+/// Nodes that can be removed.
+pub(crate) trait Prune {
+    /// Remove up to `chunk` consecutive nodes, starting at the `index`th one.
+    fn prune(&mut self, index: usize, chunk: usize);
+
+    /// Number of nodes that could be removed.
+    fn count(&self) -> usize;
+}
+
+impl<T> Prune for Vec<T> {
+    fn prune(&mut self, index: usize, chunk: usize) {
+        let end = (index + chunk).min(self.len());
+        self.drain(index..end);
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T, P: Default> Prune for syn::punctuated::Punctuated<T, P> {
+    fn prune(&mut self, index: usize, chunk: usize) {
+        *self = std::mem::take(self).into_iter()
+            .enumerate()
+            .filter(|&(i, _)| i < index || i >= index + chunk)
+            .map(|(_, node)| node)
+            .collect();
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Counts the changes a visitor comes across, so only the `target_index`th
+/// is made.
+pub(crate) struct Counter {
+    cur_index: usize,
+    target_index: usize,
+    /// Whether it's been made.
+    pub(crate) changed: bool,
+}
+
+impl Counter {
+    pub(crate) fn new(target_index: usize) -> Counter {
+        Counter { cur_index: 0, target_index, changed: false }
+    }
+
+    /// Whether this is the change to make.
+    pub(crate) fn hit(&mut self) -> bool {
+        self.cur_index += 1;
+        let hit = self.target_index == self.cur_index;
+        self.changed |= hit;
+        hit
+    }
+}
@@ -15,157 +15,93 @@
 // You should have received a copy of the GNU General Public License
 // along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::checkpoint::Cursor;
+
 /// Removes `pub`.
 /// Named after maggy - thanks Ivan.
-pub fn privatise_items<F: FnMut(&syn::File) -> Result<(),String>> (file: &mut syn::File, mut try_compile: F) {
-    let mut level = 0;
-    let mut index = 0;
-    loop {
+///
+/// The cursor is the number of nodes that had to stay `pub` so far, which is
+/// also where to carry on in the list of nodes that are still `pub`.
+pub fn privatise_items<F: FnMut(&syn::File) -> Result<(),String>> (file: &mut syn::File, cursor: &Cursor, mut try_compile: F) {
+    let [mut kept] = cursor.start([0]);
+    let mut public = Vec::new();
+    find_public(&file.items, &mut Vec::new(), &mut public);
+    for node in public.iter().skip(kept) {
+        cursor.set(&[kept]);
         let backup = file.clone();
-        if !file.items.privatise(level, &mut { index }) {
-            if index == 0 {
-                break;
-            }
-            level += 1;
-            index = 0;
-            continue;
-        }
-        if let Err(_msg) = try_compile(&file) {
+        *visibility(&mut file.items, node) = syn::Visibility::Inherited;
+        if let Err(_msg) = try_compile(file) {
             *file = backup;
-            index += 1;
-        } else {
-            // try delete next, which will be at same index now that we've
-            // deleted something
+            kept += 1;
         }
     }
 }
 
-trait Privatise {
-    fn privatise(&mut self, level: usize, index: &mut usize) -> bool;
+/// Something that might not need to be `pub`. Items are found by the indices
+/// of the modules leading to them, then their own.
+enum Public {
+    Item(Vec<usize>),
+    ImplItem(Vec<usize>, usize),
+    Field(Vec<usize>, usize),
 }
 
-fn privatise_item(item: &mut Option<&mut syn::Item>) -> bool {
-    match item {
-        Some(syn::Item::Fn(fun)) => {
-            if let syn::Visibility::Inherited = fun.vis {
-                //private already
-            } else {
-                fun.vis = syn::Visibility::Inherited;
-                return true;
-            }
-        },
-        Some(syn::Item::Type(tipe)) => {
-            if let syn::Visibility::Inherited = tipe.vis {
-                //private already
-            } else {
-                tipe.vis = syn::Visibility::Inherited;
-                return true;
-            }
-        }
-        _ => {}
-    }
-    false
+fn is_public(vis: &syn::Visibility) -> bool {
+    !matches!(vis, syn::Visibility::Inherited)
 }
 
-
-impl Privatise for Vec<syn::Item> {
-    fn privatise(&mut self, level: usize, index: &mut usize) -> bool {
-        if level == 0 {
-            if *index < self.len() {
-                privatise_item(&mut self.get_mut(*index))
-            } else {
-                *index -= self.len();
-                false
-            }
-        } else {
-            for item in self {
-                if match item {
-                    syn::Item::Mod(syn::ItemMod {
-                        content: Some((_, items)),
-                        ..
-                    }) => items.privatise(level - 1, index),
-                    syn::Item::Struct(item @ syn::ItemStruct{ .. }) => item.privatise(level - 1, index),
-                    syn::Item::Impl(syn::ItemImpl { items, .. }) => items.privatise(level - 1, index),
-                    syn::Item::Enum(item @ syn::ItemEnum{ .. }) => item.privatise(level - 1, index),
-                    _ => false,
-                } {
-                    return true;
+fn find_public(items: &[syn::Item], path: &mut Vec<usize>, public: &mut Vec<Public>) {
+    for (index, item) in items.iter().enumerate() {
+        path.push(index);
+        match item {
+            syn::Item::Fn(syn::ItemFn { vis, .. }) | syn::Item::Type(syn::ItemType { vis, .. }) if is_public(vis) => {
+                public.push(Public::Item(path.clone()));
+            },
+            syn::Item::Mod(syn::ItemMod { content: Some((_, items)), .. }) => find_public(items, path, public),
+            syn::Item::Struct(syn::ItemStruct { fields, .. }) => {
+                for (field, _) in fields.iter().enumerate().filter(|(_, field)| is_public(&field.vis)) {
+                    public.push(Public::Field(path.clone(), field));
                 }
-            }
-            false
-        }
-    }
-}
-
-impl Privatise for Vec<syn::ImplItem> {
-    fn privatise(&mut self, level: usize, index: &mut usize) -> bool {
-        if level < 5 {
-            if *index < self.len() {
-                if let Some(syn::ImplItem::Method(meth))
-                    = &mut self.get_mut(*index) {
-                    if let syn::Visibility::Inherited = meth.vis {
-                        false
-                    } else {
-                        meth.vis = syn::Visibility::Inherited;
-                        true
+            },
+            syn::Item::Impl(syn::ItemImpl { items, .. }) => {
+                for (index, item) in items.iter().enumerate() {
+                    if let syn::ImplItem::Method(syn::ImplItemMethod { vis, .. }) = item {
+                        if is_public(vis) {
+                            public.push(Public::ImplItem(path.clone(), index));
+                        }
                     }
-                } else { false }
-            } else {
-                *index -= self.len();
-                false
-            }
-        } else {
-            false
+                }
+            },
+            _ => {},
         }
+        path.pop();
     }
 }
 
-impl Privatise for syn::ItemEnum{
-    fn privatise(&mut self, _level: usize, _index: &mut usize) -> bool {
-//        if level < 5 {
-//            if *index < self.variants.len() {
-//                for (i, var) in self.variants.iter_mut().enumerate() {
-//                    if i == *index {
-//                        var.fields.each field could be pub
-//                        return privatise_item(&mut Some(var));
-//                    }
-//                }
-//            }
-//            else {
-//                *index -= self.variants.len();
-//            }
-//        }
-        false
+fn item<'a>(items: &'a mut [syn::Item], path: &[usize]) -> &'a mut syn::Item {
+    let (&index, rest) = path.split_first().expect("non-empty path");
+    match (&mut items[index], rest) {
+        (item, []) => item,
+        (syn::Item::Mod(syn::ItemMod { content: Some((_, items)), .. }), rest) => item(items, rest),
+        _ => panic!("path through something other than a module"),
     }
 }
 
-impl Privatise for syn::ItemStruct {
-    fn privatise(&mut self, level: usize, index: &mut usize) -> bool {
-        if level < 5 {
-            if let syn::Fields::Named(ref mut named) = self.fields {
-                if *index < named.named.len() {
-                    for (i, field) in &mut self.fields.iter_mut().enumerate()
-                    {
-                        if i == *index {
-                            return if let syn::Visibility::Inherited = field.vis {
-                                false
-                            } else {
-                                field.vis = syn::Visibility::Inherited;
-                                true
-                            };
-                        }
-                    }
-                    false
-                }
-                else {
-                    *index -= named.named.len();
-                    false
-                }
-            } else {
-                false
-            }
-        } else {
-            false
-        }
+fn visibility<'a>(items: &'a mut [syn::Item], node: &Public) -> &'a mut syn::Visibility {
+    match node {
+        Public::Item(path) => match item(items, path) {
+            syn::Item::Fn(syn::ItemFn { vis, .. }) | syn::Item::Type(syn::ItemType { vis, .. }) => vis,
+            _ => panic!("path to something other than a fn or type"),
+        },
+        Public::ImplItem(path, index) => match item(items, path) {
+            syn::Item::Impl(syn::ItemImpl { items, .. }) => match &mut items[*index] {
+                syn::ImplItem::Method(syn::ImplItemMethod { vis, .. }) => vis,
+                _ => panic!("path to something other than a method"),
+            },
+            _ => panic!("path to something other than an impl"),
+        },
+        Public::Field(path, index) => match item(items, path) {
+            syn::Item::Struct(syn::ItemStruct { fields, .. }) => &mut fields.iter_mut().nth(*index).expect("field").vis,
+            _ => panic!("path to something other than a struct"),
+        },
     }
-}
\ No newline at end of file
+}
@@ -14,128 +14,135 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
-use syn::punctuated::Punctuated;
+use std::collections::VecDeque;
+
+use crate::checkpoint::Cursor;
+use super::{one_at_a_time, Prune};
 
 /// Try to remove each item.
+pub fn prune_items<F: FnMut(&syn::File) -> Result<(),String>>(file: &mut syn::File, cursor: &Cursor, mut try_compile: F) {
+    prune_chunks(file, cursor, 1, one_at_a_time(&mut try_compile), |_| 1)
+}
 
-pub fn prune_items<F: FnMut(&syn::File) -> Result<(),String>>(file: &mut syn::File, mut try_compile: F) {
-    let mut level = 0;
-    let mut index = 0;
-    loop {
-        let backup = file.clone();
-        if !file.items.prune(level, &mut { index }) {
-            if index == 0 {
-                break;
-            }
-            level += 1;
-            index = 0;
-            continue;
-        }
-        if let Err(_msg) = try_compile(&file) {
-            *file = backup;
-            index += 1;
-        } else {
-            // try delete next, which will be at same index now that we've
-            // deleted something
-        }
-    }
+/// Try to remove contiguous chunks of items, delta debugging style: first
+/// halves, then quarters, and so on, until single items are tried.
+pub fn prune_items_ddmin<F: FnMut(&syn::File) -> Result<(),String>>(file: &mut syn::File, cursor: &Cursor, mut try_compile: F) {
+    prune_chunks(file, cursor, 1, one_at_a_time(&mut try_compile), |count| (count / 2).max(1))
 }
 
-trait Prune {
-    fn prune(&mut self, level: usize, index: &mut usize) -> bool;
+/// `prune_items`, or `prune_items_ddmin`, testing candidates in batches.
+/// See `try_changes`.
+pub fn prune_items_parallel<F: FnMut(&[syn::File]) -> Option<usize>>(file: &mut syn::File, cursor: &Cursor, ddmin: bool, jobs: usize, try_batch: F) {
+    if ddmin {
+        prune_chunks(file, cursor, jobs, try_batch, |count| (count / 2).max(1))
+    } else {
+        prune_chunks(file, cursor, jobs, try_batch, |_| 1)
+    }
 }
 
-impl Prune for Vec<syn::Item> {
-    fn prune(&mut self, level: usize, index: &mut usize) -> bool {
-        if level == 0 {
-            if *index < self.len() {
-                self.remove(*index);
-                true
-            } else {
-                *index -= self.len();
-                false
+/// The cursor is `[container, chunk, index]`, `container` counting the
+/// containers in the order they're visited.
+///
+/// Containers are visited breadth first, so an item is tried before anything
+/// inside it. A container's own containers are only looked up once it's done,
+/// so the paths to them are still valid when their turn comes.
+fn prune_chunks<F, C>(file: &mut syn::File, cursor: &Cursor, jobs: usize, mut try_batch: F, initial_chunk: C)
+    where F: FnMut(&[syn::File]) -> Option<usize>, C: Fn(usize) -> usize
+{
+    let [resume_container, mut resume_chunk, mut resume_index] = cursor.start([0, 0, 0]);
+    let mut queue = VecDeque::new();
+    queue.push_back(Vec::new());
+    let mut ordinal = 0;
+    while let Some(path) = queue.pop_front() {
+        let count = container(&mut file.items, &path).count();
+        if ordinal >= resume_container && count > 0 {
+            let mut chunk = match std::mem::take(&mut resume_chunk) {
+                0 => initial_chunk(count),
+                chunk => chunk,
+            };
+            loop {
+                let mut index = std::mem::take(&mut resume_index);
+                loop {
+                    let mut candidates = Vec::with_capacity(jobs);
+                    let mut indices = Vec::with_capacity(jobs);
+                    let mut next = index;
+                    while candidates.len() < jobs {
+                        let mut candidate = file.clone();
+                        let nodes = container(&mut candidate.items, &path);
+                        if next >= nodes.count() {
+                            break;
+                        }
+                        nodes.prune(next, chunk);
+                        candidates.push(candidate);
+                        indices.push(next);
+                        next += chunk;
+                    }
+                    if candidates.is_empty() {
+                        break;
+                    }
+                    cursor.set(&[ordinal, chunk, index]);
+                    if let Some(winner) = try_batch(&candidates) {
+                        // try delete next, which will be at same index now that
+                        // we've deleted something
+                        *file = candidates.swap_remove(winner);
+                        index = indices[winner];
+                    } else {
+                        index = next;
+                    }
+                }
+                if chunk == 1 {
+                    break;
+                }
+                chunk /= 2;
             }
-        } else {
-            for item in self {
-                if match item {
-                    syn::Item::Mod(syn::ItemMod {
-                        content: Some((_, items)),
-                        ..
-                    }) => items.prune(level - 1, index),
-                    syn::Item::Struct(item @ syn::ItemStruct{ .. }) => item.prune(level - 1, index),
-                    syn::Item::Impl(syn::ItemImpl { items, .. }) => items.prune(level - 1, index),
-                    syn::Item::Enum(item @ syn::ItemEnum{ .. }) => item.prune(level - 1, index),
-                    _ => false,
-                } {
-                    return true;
+        }
+
+        if let Some(items) = module(&mut file.items, &path) {
+            for (index, item) in items.iter_mut().enumerate() {
+                if prunable(item).is_some() {
+                    queue.push_back(path.iter().copied().chain(Some(index)).collect());
                 }
             }
-            false
         }
+        ordinal += 1;
     }
 }
 
-impl Prune for Vec<syn::ImplItem> {
-    fn prune(&mut self, level: usize, index: &mut usize) -> bool {
-        if level < 5 {
-            if *index < self.len() {
-                self.remove(*index);
-                true
-            } else {
-                *index -= self.len();
-                false
-            }
-        } else {
-            false
-        }
+/// What can be removed from inside `item`, if anything.
+fn prunable(item: &mut syn::Item) -> Option<&mut dyn Prune> {
+    match item {
+        syn::Item::Mod(syn::ItemMod { content: Some((_, items)), .. }) => Some(items),
+        syn::Item::Struct(syn::ItemStruct { fields: syn::Fields::Named(named), .. }) => Some(&mut named.named),
+        syn::Item::Struct(syn::ItemStruct { fields: syn::Fields::Unnamed(unnamed), .. }) => Some(&mut unnamed.unnamed),
+        syn::Item::Union(syn::ItemUnion { fields, .. }) => Some(&mut fields.named),
+        syn::Item::Enum(syn::ItemEnum { variants, .. }) => Some(variants),
+        syn::Item::Impl(syn::ItemImpl { items, .. }) => Some(items),
+        syn::Item::Trait(syn::ItemTrait { items, .. }) => Some(items),
+        syn::Item::ForeignMod(syn::ItemForeignMod { items, .. }) => Some(items),
+        _ => None,
     }
 }
 
-impl Prune for syn::ItemEnum{
-    fn prune(&mut self, level: usize, index: &mut usize) -> bool {
-        if level < 5 {
-            if *index < self.variants.len() {
-                //  dbg!(my_enum.variants.clone());
-                let mut smaller : Punctuated<syn::Variant, syn::Token![,]> = Punctuated::new();
-                for (i, pair) in self.variants.pairs().enumerate() {
-                    if i != *index {
-                        smaller.push((*pair.value()).clone());
-                    }
-                }
-                self.variants = smaller;
-                true
-            }
-            else {
-                *index -= self.variants.len();
-                false
-            }
-        } else { false }
+/// The items of the module at `path`: the indices of the modules leading
+/// to it, starting with the file itself.
+fn module<'a>(items: &'a mut Vec<syn::Item>, path: &[usize]) -> Option<&'a mut Vec<syn::Item>> {
+    match path.split_first() {
+        None => Some(items),
+        Some((&index, rest)) => match &mut items[index] {
+            syn::Item::Mod(syn::ItemMod { content: Some((_, items)), .. }) => module(items, rest),
+            _ => None,
+        },
     }
 }
 
-impl Prune for syn::ItemStruct {
-    fn prune(&mut self, level: usize, index: &mut usize) -> bool {
-        if level < 5 {
-            if let syn::Fields::Named(ref mut named) = self.fields {
-                if *index < named.named.len() {
-                    let mut smaller : Punctuated<syn::Field, syn::Token![,]> = Punctuated::new();
-                    for (i, pair) in named.named.pairs().enumerate() {
-                        if i != *index {
-                            smaller.push((*pair.value()).clone());
-                        }
-                    }
-                    named.named = smaller;
-                    true
-                }
-                else {
-                    *index -= named.named.len();
-                    false
-                }
-            } else {
-                false
-            }
-        } else {
-            false
-        }
+/// The nodes of the container at `path`: the indices of the modules leading
+/// to it, then that of the item itself. The file itself if `path` is empty.
+fn container<'a>(items: &'a mut Vec<syn::Item>, path: &[usize]) -> &'a mut dyn Prune {
+    match path.split_last() {
+        None => items,
+        Some((&index, modules)) => {
+            let items = module(items, modules).expect("path to a module");
+            prunable(&mut items[index]).expect("path to a container")
+        },
     }
-}
\ No newline at end of file
+}
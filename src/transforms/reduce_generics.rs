@@ -0,0 +1,298 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Try to remove each generic parameter, each where predicate and each bound,
+/// from items, impls and methods.
+///
+/// When a parameter of something with a name is removed, the matching
+/// argument is removed wherever that name is used with explicit generic
+/// arguments, e.g. `Foo<'a, T>` or `f::<T>()`. Only paths that lead to it
+/// from the module they're in count, so another `Foo` under `std::` or a
+/// different module keeps its arguments. Methods are recognised after
+/// `Self::` or the name of their type or trait, and in method calls.
+use std::result::Result;
+use syn::{*, visit_mut::*};
+
+use crate::checkpoint::Cursor;
+use super::{one_at_a_time, self_ty_name, try_changes, Counter, Owner, Prune};
+
+pub fn reduce_generics<F: FnMut(&File) -> Result<(),String>>(file: &mut File, cursor: &Cursor, mut try_compile: F) {
+    reduce_generics_parallel(file, cursor, 1, one_at_a_time(&mut try_compile))
+}
+
+/// `reduce_generics`, testing candidates in batches. See `try_changes`.
+pub fn reduce_generics_parallel<F: FnMut(&[File]) -> Option<usize>>(file: &mut File, cursor: &Cursor, jobs: usize, try_batch: F) {
+    let [target_index] = cursor.start([1]);
+    let change = |file: &mut File, target_index| {
+        let mut visitor = GenericsVisitor {
+            owner: None,
+            module: Vec::new(),
+            parent: None,
+            counter: Counter::new(target_index),
+            removed: None,
+        };
+        visitor.visit_file_mut(file);
+        if let Some(removed) = visitor.removed {
+            UseVisitor { removed, module: Vec::new() }.visit_file_mut(file);
+        }
+        visitor.counter.changed
+    };
+    try_changes(file, cursor, &[], target_index, jobs, change, try_batch);
+}
+
+/// Which generic arguments a parameter matches up with.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+	Lifetime,
+	/// Types and consts, which can't always be told apart in arguments.
+	TypeOrConst,
+}
+
+fn kind(param: &GenericParam) -> Kind {
+	match param {
+		GenericParam::Lifetime(_) => Kind::Lifetime,
+		GenericParam::Type(_) | GenericParam::Const(_) => Kind::TypeOrConst,
+	}
+}
+
+/// The `index`th parameter of that kind of `owner`'s generics was removed.
+struct Removed {
+	owner: Owner,
+	kind: Kind,
+	index: usize,
+}
+
+struct GenericsVisitor {
+	/// Whatever the next `Generics` belong to, if it's something that can be
+	/// referred to by name.
+	owner: Option<Owner>,
+	module: Vec<Ident>,
+	/// The type or trait whose items are being visited.
+	parent: Option<Ident>,
+	counter: Counter,
+	removed: Option<Removed>,
+}
+
+impl GenericsVisitor {
+	fn set_owner(&mut self, name: &Ident, method: bool) {
+		self.owner = Some(Owner {
+			name: name.clone(),
+			module: self.module.clone(),
+			parent: if method { self.parent.clone() } else { None },
+		});
+	}
+
+	fn change(&mut self, generics: &mut Generics, owner: Option<Owner>) {
+		for index in 0..generics.params.len() {
+			if self.counter.hit() {
+				let param_kind = kind(&generics.params[index]);
+				self.removed = owner.map(|owner| Removed {
+					owner,
+					kind: param_kind,
+					index: generics.params.iter().take(index).filter(|param| kind(param) == param_kind).count(),
+				});
+				generics.params.prune(index, 1);
+				return;
+			}
+		}
+
+		if let Some(where_clause) = &mut generics.where_clause {
+			for index in 0..where_clause.predicates.len() {
+				if self.counter.hit() {
+					where_clause.predicates.prune(index, 1);
+					if where_clause.predicates.is_empty() {
+						generics.where_clause = None;
+					}
+					return;
+				}
+			}
+		}
+
+		for param in generics.params.iter_mut() {
+			let (colon_token, len) = match param {
+				GenericParam::Type(param) => (&mut param.colon_token, param.bounds.len()),
+				GenericParam::Lifetime(param) => (&mut param.colon_token, param.bounds.len()),
+				GenericParam::Const(_) => continue,
+			};
+			for index in 0..len {
+				if self.counter.hit() {
+					if len == 1 {
+						*colon_token = None;
+					}
+					match param {
+						GenericParam::Type(param) => param.bounds.prune(index, 1),
+						GenericParam::Lifetime(param) => param.bounds.prune(index, 1),
+						GenericParam::Const(_) => unreachable!(),
+					}
+					return;
+				}
+			}
+		}
+
+		// Removing the only bound is up to removing the whole predicate.
+		for predicate in generics.where_clause.iter_mut().flat_map(|where_clause| where_clause.predicates.iter_mut()) {
+			let len = match predicate {
+				WherePredicate::Type(predicate) => predicate.bounds.len(),
+				WherePredicate::Lifetime(predicate) => predicate.bounds.len(),
+				WherePredicate::Eq(_) => continue,
+			};
+			if len < 2 {
+				continue;
+			}
+			for index in 0..len {
+				if self.counter.hit() {
+					match predicate {
+						WherePredicate::Type(predicate) => predicate.bounds.prune(index, 1),
+						WherePredicate::Lifetime(predicate) => predicate.bounds.prune(index, 1),
+						WherePredicate::Eq(_) => unreachable!(),
+					}
+					return;
+				}
+			}
+		}
+	}
+}
+
+impl VisitMut for GenericsVisitor {
+	fn visit_generics_mut(&mut self, i: &mut Generics) {
+		let owner = self.owner.take();
+		if !self.counter.changed {
+			self.change(i, owner);
+		}
+		visit_generics_mut(self, i)
+	}
+
+	fn visit_item_struct_mut(&mut self, i: &mut ItemStruct) {
+		self.set_owner(&i.ident, false);
+		visit_item_struct_mut(self, i)
+	}
+
+	fn visit_item_enum_mut(&mut self, i: &mut ItemEnum) {
+		self.set_owner(&i.ident, false);
+		visit_item_enum_mut(self, i)
+	}
+
+	fn visit_item_union_mut(&mut self, i: &mut ItemUnion) {
+		self.set_owner(&i.ident, false);
+		visit_item_union_mut(self, i)
+	}
+
+	fn visit_item_type_mut(&mut self, i: &mut ItemType) {
+		self.set_owner(&i.ident, false);
+		visit_item_type_mut(self, i)
+	}
+
+	fn visit_item_trait_mut(&mut self, i: &mut ItemTrait) {
+		self.set_owner(&i.ident, false);
+		let parent = self.parent.replace(i.ident.clone());
+		visit_item_trait_mut(self, i);
+		self.parent = parent;
+	}
+
+	fn visit_item_impl_mut(&mut self, i: &mut ItemImpl) {
+		let parent = std::mem::replace(&mut self.parent, self_ty_name(&i.self_ty));
+		visit_item_impl_mut(self, i);
+		self.parent = parent;
+	}
+
+	fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
+		self.module.push(i.ident.clone());
+		visit_item_mod_mut(self, i);
+		self.module.pop();
+	}
+
+	fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
+		self.set_owner(&i.ident, false);
+		visit_item_fn_mut(self, i)
+	}
+
+	fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
+		self.set_owner(&i.sig.ident, true);
+		visit_impl_item_method_mut(self, i)
+	}
+
+	fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
+		self.set_owner(&i.sig.ident, true);
+		visit_trait_item_method_mut(self, i)
+	}
+}
+
+/// Removes the argument for the `Removed` parameter wherever its owner is
+/// named.
+struct UseVisitor {
+	removed: Removed,
+	module: Vec<Ident>,
+}
+
+impl UseVisitor {
+	fn position<'a, T: 'a, I: Iterator<Item = &'a T>, F: Fn(&T) -> Option<Kind>>(&self, args: I, kind: F) -> Option<usize> {
+		args.enumerate()
+			.filter(|(_, arg)| kind(arg) == Some(self.removed.kind))
+			.nth(self.removed.index)
+			.map(|(position, _)| position)
+	}
+}
+
+impl VisitMut for UseVisitor {
+	fn visit_path_mut(&mut self, i: &mut Path) {
+		for index in 0..i.segments.len() {
+			if i.segments[index].ident != self.removed.owner.name {
+				continue;
+			}
+			let prefix: Vec<_> = i.segments.iter().take(index).map(|segment| &segment.ident).collect();
+			if !self.removed.owner.reached_by(&self.module, i.leading_colon.is_some(), &prefix) {
+				continue;
+			}
+			let segment = &mut i.segments[index];
+			if let PathArguments::AngleBracketed(arguments) = &mut segment.arguments {
+				let position = self.position(arguments.args.iter(), |arg| match arg {
+					GenericArgument::Lifetime(_) => Some(Kind::Lifetime),
+					GenericArgument::Type(_) | GenericArgument::Const(_) => Some(Kind::TypeOrConst),
+					GenericArgument::Binding(_) | GenericArgument::Constraint(_) => None,
+				});
+				if let Some(position) = position {
+					arguments.args.prune(position, 1);
+					if arguments.args.is_empty() {
+						segment.arguments = PathArguments::None;
+					}
+				}
+			}
+		}
+		visit_path_mut(self, i)
+	}
+
+	fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
+		self.module.push(i.ident.clone());
+		visit_item_mod_mut(self, i);
+		self.module.pop();
+	}
+
+	fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
+		if self.removed.owner.parent.is_some() && i.method == self.removed.owner.name {
+			if let Some(turbofish) = &mut i.turbofish {
+				let position = self.position(turbofish.args.iter(), |_| Some(Kind::TypeOrConst));
+				if let Some(position) = position {
+					turbofish.args.prune(position, 1);
+					if turbofish.args.is_empty() {
+						i.turbofish = None;
+					}
+				}
+			}
+		}
+		visit_expr_method_call_mut(self, i)
+	}
+}
@@ -0,0 +1,196 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Try to remove each function parameter, along with the matching argument
+/// wherever that function is called with the right number of arguments, and
+/// to remove each return type.
+///
+/// Calls are recognised by a path that leads to the function from the
+/// module they're in, or for associated functions, through `Self::` or the
+/// name of their type or trait. Method calls only go by the method's name.
+///
+/// `self` is left alone, so methods stay methods.
+use std::result::Result;
+use syn::{*, visit_mut::*};
+
+use crate::checkpoint::Cursor;
+use super::{one_at_a_time, self_ty_name, try_changes, Counter, Owner, Prune};
+
+pub fn reduce_signatures<F: FnMut(&File) -> Result<(),String>>(file: &mut File, cursor: &Cursor, mut try_compile: F) {
+    reduce_signatures_parallel(file, cursor, 1, one_at_a_time(&mut try_compile))
+}
+
+/// `reduce_signatures`, testing candidates in batches. See `try_changes`.
+pub fn reduce_signatures_parallel<F: FnMut(&[File]) -> Option<usize>>(file: &mut File, cursor: &Cursor, jobs: usize, try_batch: F) {
+    let [target_index] = cursor.start([1]);
+    let change = |file: &mut File, target_index| {
+        let mut visitor = SignatureVisitor {
+            module: Vec::new(),
+            parent: None,
+            counter: Counter::new(target_index),
+            removed: None,
+        };
+        visitor.visit_file_mut(file);
+        if let Some(removed) = visitor.removed {
+            CallVisitor { removed, module: Vec::new() }.visit_file_mut(file);
+        }
+        visitor.counter.changed
+    };
+    try_changes(file, cursor, &[], target_index, jobs, change, try_batch);
+}
+
+fn is_receiver(arg: &FnArg) -> bool {
+	match arg {
+		FnArg::SelfRef(_) | FnArg::SelfValue(_) => true,
+		FnArg::Captured(_) | FnArg::Inferred(_) | FnArg::Ignored(_) => false,
+	}
+}
+
+/// The `index`th parameter of the function or method `owner`, which had
+/// `count` of them including `self`, was removed.
+struct Removed {
+	owner: Owner,
+	index: usize,
+	count: usize,
+	receiver: bool,
+}
+
+struct SignatureVisitor {
+	module: Vec<Ident>,
+	/// The type or trait whose items are being visited.
+	parent: Option<Ident>,
+	counter: Counter,
+	removed: Option<Removed>,
+}
+
+impl SignatureVisitor {
+	fn change(&mut self, name: &Ident, associated: bool, decl: &mut FnDecl, block: Option<&mut Block>) {
+		if self.counter.changed {
+			return;
+		}
+
+		for index in 0..decl.inputs.len() {
+			if is_receiver(&decl.inputs[index]) {
+				continue;
+			}
+			if self.counter.hit() {
+				self.removed = Some(Removed {
+					owner: Owner {
+						name: name.clone(),
+						module: self.module.clone(),
+						parent: if associated { self.parent.clone() } else { None },
+					},
+					index,
+					count: decl.inputs.len(),
+					receiver: decl.inputs.iter().any(is_receiver),
+				});
+				decl.inputs.prune(index, 1);
+				return;
+			}
+		}
+
+		if let ReturnType::Type(..) = decl.output {
+			if self.counter.hit() {
+				decl.output = ReturnType::Default;
+				// so the body is `()` too
+				if let Some(Block { stmts, .. }) = block {
+					if let Some(Stmt::Expr(_)) = stmts.last() {
+						if let Some(Stmt::Expr(expr)) = stmts.pop() {
+							stmts.push(Stmt::Semi(expr, Default::default()));
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+impl VisitMut for SignatureVisitor {
+	fn visit_item_fn_mut(&mut self, i: &mut ItemFn) {
+		self.change(&i.ident, false, &mut i.decl, Some(&mut i.block));
+		visit_item_fn_mut(self, i)
+	}
+
+	fn visit_impl_item_method_mut(&mut self, i: &mut ImplItemMethod) {
+		self.change(&i.sig.ident, true, &mut i.sig.decl, Some(&mut i.block));
+		visit_impl_item_method_mut(self, i)
+	}
+
+	fn visit_trait_item_method_mut(&mut self, i: &mut TraitItemMethod) {
+		self.change(&i.sig.ident, true, &mut i.sig.decl, i.default.as_mut());
+		visit_trait_item_method_mut(self, i)
+	}
+
+	fn visit_item_impl_mut(&mut self, i: &mut ItemImpl) {
+		let parent = std::mem::replace(&mut self.parent, self_ty_name(&i.self_ty));
+		visit_item_impl_mut(self, i);
+		self.parent = parent;
+	}
+
+	fn visit_item_trait_mut(&mut self, i: &mut ItemTrait) {
+		let parent = self.parent.replace(i.ident.clone());
+		visit_item_trait_mut(self, i);
+		self.parent = parent;
+	}
+
+	fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
+		self.module.push(i.ident.clone());
+		visit_item_mod_mut(self, i);
+		self.module.pop();
+	}
+}
+
+/// Removes the argument for the `Removed` parameter from calls to its
+/// function.
+struct CallVisitor {
+	removed: Removed,
+	module: Vec<Ident>,
+}
+
+impl VisitMut for CallVisitor {
+	fn visit_expr_call_mut(&mut self, i: &mut ExprCall) {
+		let calls_removed = match &*i.func {
+			Expr::Path(ExprPath { path, .. }) => {
+				let idents: Vec<_> = path.segments.iter().map(|segment| &segment.ident).collect();
+				match idents.split_last() {
+					Some((name, prefix)) => **name == self.removed.owner.name
+						&& self.removed.owner.reached_by(&self.module, path.leading_colon.is_some(), prefix),
+					None => false,
+				}
+			},
+			_ => false,
+		};
+		// `Type::method(receiver, ...)` passes `self` like any other argument
+		if calls_removed && i.args.len() == self.removed.count {
+			i.args.prune(self.removed.index, 1);
+		}
+		visit_expr_call_mut(self, i)
+	}
+
+	fn visit_expr_method_call_mut(&mut self, i: &mut ExprMethodCall) {
+		if self.removed.receiver && i.method == self.removed.owner.name && i.args.len() + 1 == self.removed.count {
+			i.args.prune(self.removed.index - 1, 1);
+		}
+		visit_expr_method_call_mut(self, i)
+	}
+
+	fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
+		self.module.push(i.ident.clone());
+		visit_item_mod_mut(self, i);
+		self.module.pop();
+	}
+}
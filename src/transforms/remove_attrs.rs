@@ -0,0 +1,97 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Try to remove each attribute on its own, e.g. `#[inline]`, `#[repr(C)]`
+/// or `#[cfg_attr(...)]`.
+///
+/// Attributes whose path is in `keep` are never touched. If `only` isn't
+/// empty, just the attributes whose path is in there are tried. Otherwise,
+/// `#[cfg]` is left alone too: removing it switches code on rather than
+/// taking it away. Paths are written like in the source, e.g. `inline` or
+/// `rustfmt::skip`.
+use std::result::Result;
+use syn::{*, visit_mut::*};
+
+use crate::checkpoint::Cursor;
+use super::{one_at_a_time, try_changes};
+
+pub fn remove_attrs<F: FnMut(&File) -> Result<(),String>>(file: &mut File, cursor: &Cursor, keep: &[String], only: &[String], mut try_compile: F) {
+    remove_attrs_parallel(file, cursor, keep, only, 1, one_at_a_time(&mut try_compile))
+}
+
+/// `remove_attrs`, testing candidates in batches. See `try_changes`.
+pub fn remove_attrs_parallel<F: FnMut(&[File]) -> Option<usize>>(file: &mut File, cursor: &Cursor, keep: &[String], only: &[String], jobs: usize, try_batch: F) {
+    let [target_index] = cursor.start([1]);
+    let change = |file: &mut File, target_index| {
+        let mut visitor = AttrContainerVisitor {
+            keep,
+            only,
+            cur_index: 0,
+            target_index,
+            changed: false,
+        };
+        visitor.visit_file_mut(file);
+        visitor.changed
+    };
+    try_changes(file, cursor, &[], target_index, jobs, change, try_batch);
+}
+
+fn attr_path(attr: &Attribute) -> String {
+	attr.path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>().join("::")
+}
+
+struct AttrContainerVisitor<'a> {
+	keep: &'a [String],
+	only: &'a [String],
+	cur_index: usize,
+	target_index: usize,
+	changed: bool,
+}
+
+impl AttrContainerVisitor<'_> {
+	fn removable(&self, attr: &Attribute) -> bool {
+		let path = attr_path(attr);
+		if self.keep.contains(&path) {
+			false
+		} else if self.only.is_empty() {
+			path != "cfg"
+		} else {
+			self.only.contains(&path)
+		}
+	}
+
+	fn visit_attr_container(&mut self, attrs: &mut Vec<Attribute>) {
+		if self.changed {
+			return;
+		}
+
+		for attr_index in 0..attrs.len() {
+			if !self.removable(&attrs[attr_index]) {
+				continue;
+			}
+			self.cur_index += 1;
+
+			if self.target_index == self.cur_index {
+				attrs.remove(attr_index);
+				self.changed = true;
+				return;
+			}
+		}
+	}
+}
+
+impl_VisitMut_attrs!(AttrContainerVisitor<'_>);
@@ -0,0 +1,81 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Try to remove the provided body of each trait method, leaving just its
+/// signature.
+use std::mem;
+
+use syn::visit_mut::*;
+
+use crate::checkpoint::Cursor;
+
+pub fn remove_default_bodies<F: FnMut(&syn::File) -> Result<(),String>>(file: &mut syn::File, cursor: &Cursor, mut try_compile: F) {
+    let [target_index] = cursor.start([1]);
+    let mut visitor = TraitMethodVisitor {
+        backup: None,
+        cur_index: 0,
+        target_index,
+    };
+
+    loop {
+        visitor.cur_index = 0;
+        visit_file_mut(&mut visitor, file);
+
+        // no more changes to be made
+        if visitor.backup.is_none() {
+            break
+        }
+
+        cursor.set(&[visitor.target_index]);
+        if let Ok(()) = try_compile(file) {
+            // this change works, keep it!
+            visitor.backup = None;
+        }
+    }
+}
+
+struct TraitMethodVisitor {
+	backup: Option<syn::TraitItemMethod>,
+	cur_index: usize,
+	target_index: usize,
+}
+
+impl VisitMut for TraitMethodVisitor {
+	fn visit_trait_item_method_mut(&mut self, i: &mut syn::TraitItemMethod) {
+		self.cur_index += 1;
+
+		if self.target_index == self.cur_index {
+			if let Some(backup) = self.backup.take() {
+				// the change we tried didn't work. revert and try the next
+				// possible change
+				*i = backup;
+			} else if i.default.is_some() {
+				let without_body = syn::TraitItemMethod {
+					default: None,
+					semi_token: Some(Default::default()),
+					..i.clone()
+				};
+				self.backup = Some(mem::replace(i, without_body));
+				return;
+			}
+
+			self.target_index += 1;
+		}
+
+		visit_trait_item_method_mut(self, i)
+	}
+}
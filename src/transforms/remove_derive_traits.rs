@@ -0,0 +1,139 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Try to remove each trait from `#[derive(...)]` attributes, removing the
+/// attribute once it's empty.
+use std::result::Result;
+use syn::{*, parse::{Parse, ParseStream}, punctuated::Punctuated, visit_mut::*};
+use quote::quote;
+
+use crate::checkpoint::Cursor;
+
+pub fn remove_derive_traits<F: FnMut(&File) -> Result<(),String>>(file: &mut File, cursor: &Cursor, mut try_compile: F) {
+    let [target_index] = cursor.start([1]);
+    let mut visitor = DeriveVisitor {
+        backup: None,
+        cur_container: 0,
+        cur_index: 0,
+        target_index,
+    };
+
+    loop {
+        visitor.cur_container = 0;
+        visitor.cur_index = 0;
+
+        visit_file_mut(&mut visitor, file);
+
+        // no more changes to be made
+        if visitor.backup.is_none() {
+            break
+        }
+
+        cursor.set(&[visitor.target_index]);
+        if let Ok(()) = try_compile(file) {
+            // this change works, keep it!
+            visitor.backup = None;
+        }
+    }
+}
+
+/// The contents of `#[derive(...)]`.
+struct DeriveList(Punctuated<Path, Token![,]>);
+
+impl Parse for DeriveList {
+	fn parse(input: ParseStream) -> syn::parse::Result<Self> {
+		let content;
+		parenthesized!(content in input);
+		Ok(DeriveList(content.parse_terminated(Path::parse_mod_style)?))
+	}
+}
+
+fn derive_list(attr: &Attribute) -> Option<DeriveList> {
+	if attr.path.is_ident("derive") {
+		syn::parse2(attr.tts.clone()).ok()
+	} else {
+		None
+	}
+}
+
+struct DeriveVisitor {
+	/// The attributes of the `cur_container`th node as they were before the
+	/// change being tried.
+	backup: Option<(usize, Vec<Attribute>)>,
+	cur_container: usize,
+	cur_index: usize,
+	target_index: usize,
+}
+
+impl DeriveVisitor {
+	fn visit_attr_container(&mut self, attrs: &mut Vec<Attribute>) {
+		self.cur_container += 1;
+
+		if let Some((container, backup)) = self.backup.take() {
+			if container == self.cur_container {
+				// the change we tried didn't work. revert and try the next
+				// possible change
+				*attrs = backup;
+				self.target_index += 1;
+			} else {
+				self.backup = Some((container, backup));
+			}
+		}
+
+		for attr_index in 0..attrs.len() {
+			let DeriveList(traits) = match derive_list(&attrs[attr_index]) {
+				Some(list) => list,
+				None => continue,
+			};
+			for trait_index in 0..traits.len() {
+				self.cur_index += 1;
+
+				if self.target_index == self.cur_index && self.backup.is_none() {
+					self.backup = Some((self.cur_container, attrs.clone()));
+					let rest: Punctuated<Path, Token![,]> = traits.into_iter()
+						.enumerate()
+						.filter(|&(i, _)| i != trait_index)
+						.map(|(_, path)| path)
+						.collect();
+					if rest.is_empty() {
+						attrs.remove(attr_index);
+					} else {
+						attrs[attr_index].tts = quote!((#rest));
+					}
+					return;
+				}
+			}
+		}
+	}
+}
+
+impl VisitMut for DeriveVisitor {
+	fn visit_item_struct_mut(&mut self, i: &mut ItemStruct) {
+		self.visit_attr_container(&mut i.attrs);
+		visit_item_struct_mut(self, i);
+	}
+
+	fn visit_item_enum_mut(&mut self, i: &mut ItemEnum) {
+		self.visit_attr_container(&mut i.attrs);
+		visit_item_enum_mut(self, i);
+	}
+
+	fn visit_item_union_mut(&mut self, i: &mut ItemUnion) {
+		self.visit_attr_container(&mut i.attrs);
+		visit_item_union_mut(self, i);
+	}
+}
@@ -0,0 +1,106 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Try to remove statements from each block, delta debugging style: first in
+/// chunks as large as the longest block, then halving the chunk size until
+/// single statements are tried. A block's tail expression is left alone, so
+/// its type doesn't change.
+use std::result::Result;
+use syn::{*, visit_mut::*};
+
+use crate::checkpoint::Cursor;
+use super::{one_at_a_time, try_changes};
+
+/// The cursor is `[chunk, target_index]`.
+pub fn remove_stmts<F: FnMut(&File) -> Result<(),String>>(file: &mut File, cursor: &Cursor, mut try_compile: F) {
+    remove_stmts_parallel(file, cursor, 1, one_at_a_time(&mut try_compile))
+}
+
+/// `remove_stmts`, testing candidates in batches. See `try_changes`.
+pub fn remove_stmts_parallel<F: FnMut(&[File]) -> Option<usize>>(file: &mut File, cursor: &Cursor, jobs: usize, mut try_batch: F) {
+    let mut longest = LongestBlock(0);
+    longest.visit_file_mut(file);
+    if longest.0 == 0 {
+        return;
+    }
+
+    // the largest power of two that fits the longest block
+    let initial_chunk = 1 << (usize::BITS - 1 - longest.0.leading_zeros());
+    let [mut chunk, mut target_index] = cursor.start([initial_chunk, 1]);
+
+    while chunk > 0 {
+        let change = |file: &mut File, target_index| {
+            let mut visitor = BlockVisitor {
+                chunk,
+                cur_index: 0,
+                target_index,
+                changed: false,
+            };
+            visitor.visit_file_mut(file);
+            visitor.changed
+        };
+        try_changes(file, cursor, &[chunk], target_index, jobs, change, &mut try_batch);
+
+        chunk /= 2;
+        target_index = 1;
+    }
+}
+
+/// The statements that may be removed, i.e. all but the tail expression.
+fn removable(block: &Block) -> usize {
+	match block.stmts.last() {
+		Some(Stmt::Expr(_)) => block.stmts.len() - 1,
+		_ => block.stmts.len(),
+	}
+}
+
+struct LongestBlock(usize);
+
+impl VisitMut for LongestBlock {
+	fn visit_block_mut(&mut self, i: &mut Block) {
+		self.0 = self.0.max(removable(i));
+		visit_block_mut(self, i)
+	}
+}
+
+struct BlockVisitor {
+	chunk: usize,
+	cur_index: usize,
+	target_index: usize,
+	changed: bool,
+}
+
+impl VisitMut for BlockVisitor {
+	fn visit_block_mut(&mut self, i: &mut Block) {
+		if self.changed {
+			return;
+		}
+
+		let removable = removable(i);
+		for start in (0..removable).step_by(self.chunk) {
+			self.cur_index += 1;
+
+			if self.target_index == self.cur_index {
+				i.stmts.drain(start..removable.min(start + self.chunk));
+				self.changed = true;
+				return;
+			}
+		}
+
+		visit_block_mut(self, i)
+	}
+}
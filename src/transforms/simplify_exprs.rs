@@ -0,0 +1,102 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+/// Try to replace each expression with something simpler: an `if` with one of
+/// its branches, a `match` with one of its arms, a binary operation with one
+/// of its operands, a method call with its receiver, a literal with `0`, `""`
+/// or `()`, and a call with `unimplemented!()`.
+///
+/// Replacements are only tried if they're shorter, so this always finishes.
+use std::result::Result;
+use syn::{*, visit_mut::*};
+use quote::ToTokens;
+
+use crate::checkpoint::Cursor;
+use super::{one_at_a_time, try_changes};
+
+pub fn simplify_exprs<F: FnMut(&File) -> Result<(),String>>(file: &mut File, cursor: &Cursor, mut try_compile: F) {
+    simplify_exprs_parallel(file, cursor, 1, one_at_a_time(&mut try_compile))
+}
+
+/// `simplify_exprs`, testing candidates in batches. See `try_changes`.
+pub fn simplify_exprs_parallel<F: FnMut(&[File]) -> Option<usize>>(file: &mut File, cursor: &Cursor, jobs: usize, try_batch: F) {
+    let [target_index] = cursor.start([1]);
+    let change = |file: &mut File, target_index| {
+        let mut visitor = ExprVisitor {
+            cur_index: 0,
+            target_index,
+            changed: false,
+        };
+        visitor.visit_file_mut(file);
+        visitor.changed
+    };
+    try_changes(file, cursor, &[], target_index, jobs, change, try_batch);
+}
+
+fn size(expr: &Expr) -> usize {
+	expr.into_token_stream().to_string().len()
+}
+
+fn block_expr(block: &Block) -> Expr {
+	Expr::Block(ExprBlock { attrs: Vec::new(), label: None, block: block.clone() })
+}
+
+/// What `expr` could be replaced with, simplest first.
+fn simplifications(expr: &Expr) -> Vec<Expr> {
+	let mut candidates = match expr {
+		Expr::If(expr) => {
+			let mut branches = vec![block_expr(&expr.then_branch)];
+			branches.extend(expr.else_branch.as_ref().map(|(_, branch)| (**branch).clone()));
+			branches
+		},
+		Expr::Match(expr) => expr.arms.iter().map(|arm| (*arm.body).clone()).collect(),
+		Expr::Binary(expr) => vec![(*expr.left).clone(), (*expr.right).clone()],
+		Expr::MethodCall(expr) => vec![(*expr.receiver).clone()],
+		Expr::Lit(_) => vec![parse_quote!(0), parse_quote!(""), parse_quote!(())],
+		Expr::Call(_) => vec![parse_quote!(unimplemented!())],
+		_ => Vec::new(),
+	};
+	let size = size(expr);
+	candidates.retain(|candidate| self::size(candidate) < size);
+	candidates
+}
+
+struct ExprVisitor {
+	cur_index: usize,
+	target_index: usize,
+	changed: bool,
+}
+
+impl VisitMut for ExprVisitor {
+	fn visit_expr_mut(&mut self, i: &mut Expr) {
+		if self.changed {
+			return;
+		}
+
+		for candidate in simplifications(i) {
+			self.cur_index += 1;
+
+			if self.target_index == self.cur_index {
+				*i = candidate;
+				self.changed = true;
+				return;
+			}
+		}
+
+		visit_expr_mut(self, i)
+	}
+}
@@ -0,0 +1,157 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Undo `syn_inline_mod`: split a reduced file back into the `mod` files it
+//! was inlined from.
+
+use std::path::{Path, PathBuf};
+
+/// An out-of-line `mod foo;` and the file its contents were loaded from.
+#[derive(Debug)]
+pub(crate) struct ModFile {
+    /// Module names from the crate root, e.g. `["foo", "bar"]` for `foo::bar`.
+    pub(crate) mod_path: Vec<String>,
+    /// The declaring item's `#[path]`, which tells apart modules with the
+    /// same name under different `#[cfg]`s.
+    pub(crate) attr_path: Option<PathBuf>,
+    pub(crate) file: PathBuf,
+}
+
+/// Find all out-of-line modules reachable from `entry`, resolving paths the
+/// same way `syn_inline_mod` does.
+pub(crate) fn mod_files(entry: &Path) -> Vec<ModFile> {
+    let mut out = Vec::new();
+    if let Some(file) = parse(entry) {
+        walk(&file.items, entry, true, &mut Vec::new(), &mut Vec::new(), &mut out);
+    }
+    out
+}
+
+fn parse(path: &Path) -> Option<syn::File> {
+    syn::parse_file(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+/// A component of the directory a module's file is looked up in.
+enum Segment {
+    Ident(String),
+    Path(PathBuf),
+}
+
+fn attr_path(item: &syn::ItemMod) -> Option<PathBuf> {
+    for attr in &item.attrs {
+        if let Ok(syn::Meta::NameValue(name_value)) = attr.parse_meta() {
+            if name_value.ident == "path" {
+                if let syn::Lit::Str(path) = name_value.lit {
+                    return Some(path.value().into());
+                }
+            }
+        }
+    }
+    None
+}
+
+fn segment(item: &syn::ItemMod) -> Segment {
+    match attr_path(item) {
+        Some(path) => Segment::Path(path),
+        None => Segment::Ident(item.ident.to_string()),
+    }
+}
+
+fn candidates(base: &Path, root: bool, context: &[Segment]) -> Vec<PathBuf> {
+    let mut dir = base.parent().map(Path::to_owned).unwrap_or_default();
+    if !root && base.file_name().is_some_and(|name| name != "mod.rs") {
+        dir.push(base.file_stem().expect("file has a name"));
+    }
+    for segment in context {
+        match segment {
+            Segment::Ident(ident) => dir.push(ident),
+            Segment::Path(path) => dir.push(path),
+        }
+    }
+    match context.last() {
+        Some(Segment::Ident(_)) => vec![dir.with_extension("rs"), dir.join("mod.rs")],
+        _ => vec![dir],
+    }
+}
+
+fn walk(items: &[syn::Item], base: &Path, root: bool, context: &mut Vec<Segment>, mod_path: &mut Vec<String>, out: &mut Vec<ModFile>) {
+    for item in items {
+        if let syn::Item::Mod(item) = item {
+            context.push(segment(item));
+            mod_path.push(item.ident.to_string());
+            match &item.content {
+                Some((_, items)) => walk(items, base, root, context, mod_path, out),
+                None => {
+                    let found = candidates(base, root, context).into_iter()
+                        .find(|path| path.exists());
+                    if let Some(path) = found {
+                        if let Some(file) = parse(&path) {
+                            walk(&file.items, &path, false, &mut Vec::new(), mod_path, out);
+                        }
+                        out.push(ModFile { mod_path: mod_path.clone(), attr_path: attr_path(item), file: path });
+                    }
+                }
+            }
+            mod_path.pop();
+            context.pop();
+        }
+    }
+}
+
+/// Turn every module in `mods` that's still in `file` back into `mod foo;`,
+/// returning the contents for each of their files. Modules that were pruned
+/// or became empty stay inline (if at all) and get no file.
+///
+/// A module is matched up with the `ModFile` for the same path and
+/// `#[path]`, so if its `#[path]` was removed while reducing, or it's
+/// declared more than once without one, it stays inline too.
+pub(crate) fn split(file: &mut syn::File, mods: &[(&ModFile, PathBuf)]) -> Vec<(PathBuf, syn::File)> {
+    let mut out = Vec::new();
+    split_items(&mut file.items, &mut Vec::new(), mods, &mut vec![false; mods.len()], &mut out);
+    out
+}
+
+fn split_items(items: &mut [syn::Item], mod_path: &mut Vec<String>, mods: &[(&ModFile, PathBuf)], used: &mut [bool], out: &mut Vec<(PathBuf, syn::File)>) {
+    for item in items {
+        if let syn::Item::Mod(item) = item {
+            let attr_path = attr_path(item);
+            if let Some((_, content)) = &mut item.content {
+                mod_path.push(item.ident.to_string());
+                split_items(content, mod_path, mods, used, out);
+                let found = mods.iter().zip(used.iter_mut())
+                    .find(|((mod_file, _), used)| !**used && mod_file.mod_path == *mod_path && mod_file.attr_path == attr_path);
+                if let Some(((_, dest), used)) = found {
+                    *used = true;
+                    // The file's inner attributes were appended to the `mod`
+                    // item's attributes when it was inlined.
+                    let (inner, outer) = std::mem::take(&mut item.attrs).into_iter()
+                        .partition(|attr| attr.style != syn::AttrStyle::Outer);
+                    item.attrs = outer;
+                    if content.is_empty() && inner.is_empty() {
+                        // leave it as `mod foo {}`
+                    } else {
+                        let items = std::mem::take(content);
+                        item.content = None;
+                        item.semi = Some(Default::default());
+                        out.push((dest.clone(), syn::File { shebang: None, attrs: inner, items }));
+                    }
+                }
+                mod_path.pop();
+            }
+        }
+    }
+}
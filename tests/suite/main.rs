@@ -16,6 +16,7 @@
 // along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::{env, fs, path::PathBuf, process::Command};
+use std::time::{Duration, Instant};
 
 fn run_test(dir: &str) {
     let mut path = tests_dir();
@@ -23,7 +24,7 @@ fn run_test(dir: &str) {
 
     let cmd = format!("{:?} -- test.sh input.rs", find_rust_reduce());
     let out = Command::new(find_rust_reduce())
-        .args(&["-"])
+        .args(["-"])
         .args(&[path.join("test.sh"), path.join("input.rs")])
         .output()
         .unwrap();
@@ -70,3 +71,47 @@ fn tests_dir() -> PathBuf {
     path.pop();
     path
 }
+
+/// Commands run with a timeout get their own process group, which Ctrl-C
+/// doesn't reach, so they have to be killed on the way out.
+#[test]
+#[cfg(target_os = "linux")]
+fn interrupt_kills_running_command() {
+    let dir = tempdir::TempDir::new("rust-reduce").unwrap();
+    let pid_file = dir.path().join("pid");
+    let script = dir.path().join("test.sh");
+    fs::write(&script, format!("sleep 60 & echo $! > {:?}\nwait\n", pid_file)).unwrap();
+    let input = dir.path().join("input.rs");
+    fs::write(&input, "fn main() {}\n").unwrap();
+
+    let mut child = Command::new(find_rust_reduce())
+        .args(["--timeout", "120", "--", "sh"])
+        .arg(&script)
+        .arg(&input)
+        .spawn()
+        .unwrap();
+
+    let start = Instant::now();
+    let pid = loop {
+        match fs::read_to_string(&pid_file) {
+            Ok(pid) if pid.ends_with('\n') => break pid.trim().to_owned(),
+            _ => {
+                assert!(start.elapsed() < Duration::from_secs(30), "test never ran");
+                std::thread::sleep(Duration::from_millis(50));
+            },
+        }
+    };
+    let kill = Command::new("kill").args(["-INT", &child.id().to_string()]).status().unwrap();
+    assert!(kill.success());
+    assert_eq!(child.wait().unwrap().code(), Some(130));
+
+    // Gone, or at most waiting for whoever inherited it to reap it.
+    let running = || fs::read_to_string(format!("/proc/{}/stat", pid))
+        .is_ok_and(|stat| !stat.rsplit(')').next().unwrap_or("").trim_start().starts_with('Z'));
+    let start = Instant::now();
+    while running() {
+        assert!(start.elapsed() < Duration::from_secs(10), "sleep {} is still running", pid);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(fs::read_to_string(&input).unwrap(), "fn main() {}\n");
+}
@@ -0,0 +1,356 @@
+// Copyright (c) Jethro G. Beekman
+//
+// This file is part of rust-reduce.
+//
+// rust-reduce is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published
+// by the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// rust-reduce is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with rust-reduce.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Runs the passes on small files, with the interestingness test written as
+//! a closure.
+
+use quote::ToTokens;
+use rust_reduce::Cursor;
+use rust_reduce::transforms::*;
+
+fn parse(source: &str) -> syn::File {
+    syn::parse_str(source).expect("valid Rust")
+}
+
+fn source(file: &syn::File) -> String {
+    file.into_token_stream().to_string()
+}
+
+/// Interesting if it contains all of `needles`, e.g. `fn c`.
+fn contains<'a>(needles: &'a [&str]) -> impl FnMut(&syn::File) -> Result<(), String> + 'a {
+    move |file| {
+        let source = source(file);
+        match needles.iter().find(|needle| !source.contains(*needle)) {
+            Some(needle) => Err(format!("no {}", needle)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn assert_reduced(file: &syn::File, expected: &str) {
+    assert_eq!(source(file), source(&parse(expected)));
+}
+
+/// The names of the functions in `items`, `mod`s included.
+fn fns(items: &[syn::Item]) -> Vec<String> {
+    let mut names = Vec::new();
+    for item in items {
+        match item {
+            syn::Item::Fn(item) => names.push(item.ident.to_string()),
+            syn::Item::Mod(syn::ItemMod { content: Some((_, items)), .. }) => names.extend(fns(items)),
+            _ => {},
+        }
+    }
+    names
+}
+
+#[test]
+fn ddmin_halves_chunks_within_each_container() {
+    let mut file = parse("fn a() {} fn b() {} fn c() {} fn d() {} mod m { fn e() {} fn f() {} }");
+    let mut tried = Vec::new();
+    let mut interesting = contains(&["fn c", "fn f"]);
+    prune_items::prune_items_ddmin(&mut file, &Cursor::default(), |candidate| {
+        tried.push(fns(&candidate.items).join(" "));
+        interesting(candidate)
+    });
+
+    assert_eq!(tried, vec![
+        // halves of the 5 items in the file, then of what's left
+        "c d e f",
+        "e f",
+        "c d",
+        // single items
+        "d e f",
+        "c e f",
+        "c",
+        // then the module's items, which are never removed together with
+        // the file's
+        "c f",
+        "c",
+    ]);
+    assert_reduced(&file, "fn c() {} mod m { fn f() {} }");
+}
+
+#[test]
+fn prune_items_prunes_inside_traits_foreign_blocks_unions_and_tuple_structs() {
+    let mut file = parse(r#"
+        trait T { fn a(); fn b(); type C; }
+        extern "C" { fn d(); fn e(); }
+        union U { f: u8, g: u16 }
+        struct S(u8, u16, u32);"#);
+    prune_items::prune_items(&mut file, &Cursor::default(), contains(&["fn a", "fn e", "g : u16", "u32"]));
+    assert_reduced(&file, r#"
+        trait T { fn a(); }
+        extern "C" { fn e(); }
+        union U { g: u16 }
+        struct S(u32);"#);
+}
+
+#[test]
+fn batches_keep_the_first_interesting_candidate() {
+    let input = "fn f() { a(); b(); c(); d(); e(); }";
+    let mut serial = parse(input);
+    remove_stmts::remove_stmts(&mut serial, &Cursor::default(), contains(&["b ( )", "d ( )"]));
+    assert_reduced(&serial, "fn f() { b(); d(); }");
+
+    for jobs in 2..=6 {
+        let mut file = parse(input);
+        let mut interesting = contains(&["b ( )", "d ( )"]);
+        remove_stmts::remove_stmts_parallel(&mut file, &Cursor::default(), jobs, |candidates| {
+            assert!(candidates.len() <= jobs);
+            candidates.iter().position(|candidate| interesting(candidate).is_ok())
+        });
+        assert_eq!(source(&file), source(&serial), "with {} jobs", jobs);
+    }
+}
+
+#[test]
+fn remove_attrs_keeps_and_only_tries_the_given_paths() {
+    let input = "#[repr(C)] #[derive(Clone)] struct S; #[cfg(test)] #[inline] fn f() { #[allow(unused)] let x = 1; }";
+    let mut file = parse(input);
+    remove_attrs::remove_attrs(&mut file, &Cursor::default(), &["repr".to_owned()], &[], |_| Ok(()));
+    // `cfg` is only removed when asked for
+    assert_reduced(&file, "#[repr(C)] struct S; #[cfg(test)] fn f() { let x = 1; }");
+
+    let mut file = parse(input);
+    remove_attrs::remove_attrs(&mut file, &Cursor::default(), &[], &["cfg".to_owned(), "inline".to_owned()], |_| Ok(()));
+    assert_reduced(&file, "#[repr(C)] #[derive(Clone)] struct S; fn f() { #[allow(unused)] let x = 1; }");
+}
+
+#[test]
+fn remove_derive_traits_removes_one_trait_at_a_time() {
+    let mut file = parse("#[derive(Clone, Debug)] #[derive(Copy)] struct S; #[derive(PartialEq)] enum E {}");
+    let mut tried = Vec::new();
+    let mut interesting = contains(&["Debug"]);
+    remove_derive_traits::remove_derive_traits(&mut file, &Cursor::default(), |candidate| {
+        tried.push(source(candidate));
+        interesting(candidate)
+    });
+
+    let expected: Vec<_> = [
+        "#[derive(Debug)] #[derive(Copy)] struct S; #[derive(PartialEq)] enum E {}",
+        // an attribute goes as soon as its last trait does
+        "#[derive(Copy)] struct S; #[derive(PartialEq)] enum E {}",
+        "#[derive(Debug)] struct S; #[derive(PartialEq)] enum E {}",
+        "#[derive(Debug)] struct S; enum E {}",
+    ].iter().map(|expected| source(&parse(expected))).collect();
+    assert_eq!(tried, expected);
+    assert_reduced(&file, "#[derive(Debug)] struct S; enum E {}");
+}
+
+/// Whether every method of the trait without a default body is in the
+/// impl, as the compiler would insist.
+fn impl_is_complete(file: &syn::File) -> Result<(), String> {
+    let mut provided = Vec::new();
+    let mut required = Vec::new();
+    for item in &file.items {
+        match item {
+            syn::Item::Trait(item) => for item in &item.items {
+                if let syn::TraitItem::Method(method) = item {
+                    if method.default.is_none() {
+                        required.push(method.sig.ident.clone());
+                    }
+                }
+            },
+            syn::Item::Impl(item) => for item in &item.items {
+                if let syn::ImplItem::Method(method) = item {
+                    provided.push(method.sig.ident.clone());
+                }
+            },
+            _ => {},
+        }
+    }
+    match required.iter().find(|ident| !provided.contains(ident)) {
+        Some(ident) => Err(format!("missing {}", ident)),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn remove_default_bodies_keeps_the_ones_the_impl_needs() {
+    let mut file = parse("trait T { fn a() -> u8 { 1 } fn b() {} fn c(); } struct S; impl T for S { fn a() -> u8 { 2 } fn c() {} }");
+    remove_default_bodies::remove_default_bodies(&mut file, &Cursor::default(), impl_is_complete);
+    assert_reduced(&file, "trait T { fn a() -> u8; fn b() {} fn c(); } struct S; impl T for S { fn a() -> u8 { 2 } fn c() {} }");
+}
+
+#[test]
+fn privatise_items_reaches_deeply_nested_items_and_tuple_fields() {
+    let nested = |inner: &str| (0..7).fold(inner.to_owned(), |inner, depth| format!("mod m{} {{ {} }}", depth, inner));
+    let mut file = parse(&nested("pub fn keep() {} pub fn drop() {} pub type T = u8; pub struct S(pub u8); impl S { pub fn new() {} }"));
+    privatiser::privatise_items(&mut file, &Cursor::default(), contains(&["pub fn keep"]));
+    assert_reduced(&file, &nested("pub fn keep() {} fn drop() {} type T = u8; pub struct S(u8); impl S { fn new() {} }"));
+}
+
+#[test]
+fn cfg_set_parses_specs_and_evaluates_predicates() {
+    let mut cfg = eval_cfg::CfgSet::default();
+    cfg.insert_spec("unix").unwrap();
+    cfg.insert_spec(r#"feature = "std""#).unwrap();
+    assert!(cfg.contains("feature", Some("std")));
+    assert!(cfg.insert_spec("feature=std").is_err());
+    assert!(cfg.insert_spec("a-b").is_err());
+
+    let eval = |predicate: &str| cfg.eval(&syn::parse_str(predicate).expect("valid predicate"));
+    assert_eq!(eval("unix"), Some(true));
+    assert_eq!(eval("windows"), Some(false));
+    assert_eq!(eval(r#"all(unix, feature = "std")"#), Some(true));
+    assert_eq!(eval("any(windows, not(unix))"), Some(false));
+    assert_eq!(eval("any(windows, unknown(unix))"), None);
+}
+
+#[test]
+fn cfg_set_from_args_adds_cfgs_and_features_to_rustcs() {
+    let cfg = eval_cfg::CfgSet::from_args(None, vec!["foo", r#"bar = "1""#], Some("std, alloc")).unwrap();
+    assert!(cfg.contains("foo", None));
+    assert!(cfg.contains("bar", Some("1")));
+    assert!(cfg.contains("feature", Some("std")));
+    assert!(cfg.contains("feature", Some("alloc")));
+    // from rustc itself
+    assert!(cfg.contains("debug_assertions", None));
+    assert!(eval_cfg::CfgSet::from_args(None, vec!["bar=1"], None).is_err());
+}
+
+#[test]
+fn eval_cfg_resolves_items_statements_and_expressions() {
+    let mut cfg = eval_cfg::CfgSet::default();
+    cfg.insert("unix", None);
+    let input = "
+        #[cfg(unix)] fn a() {}
+        #[cfg(windows)] fn b() {}
+        #[cfg_attr(unix, inline)] #[cfg_attr(windows, cold)] fn c() {
+            #[cfg(windows)] let x = 1;
+            #[cfg(unix)] d();
+            #[cfg(windows)] e();
+            f(#[cfg(windows)] 1, 2);
+        }";
+
+    let mut file = parse(input);
+    eval_cfg::eval_cfg(&mut file, &Cursor::default(), &cfg, |_| Ok(()));
+    assert_reduced(&file, "fn a() {} #[inline] fn c() { d(); f(2); }");
+
+    // if everything at once isn't interesting, the rest still goes
+    let mut file = parse(input);
+    eval_cfg::eval_cfg(&mut file, &Cursor::default(), &cfg, contains(&["# [ cfg ( windows ) ] fn b"]));
+    assert_reduced(&file, "fn a() {} #[cfg(windows)] fn b() {} #[inline] fn c() { d(); f(2); }");
+}
+
+#[test]
+fn simplify_exprs_replaces_expressions_with_simpler_ones() {
+    let mut file = parse(r#"fn f() -> u32 { if c() { g(x.len() + 100) } else { h("long string") } }"#);
+    // until nothing changes, like the rounds do
+    loop {
+        let before = source(&file);
+        simplify_exprs::simplify_exprs(&mut file, &Cursor::default(), contains(&["g ("]));
+        if source(&file) == before {
+            break;
+        }
+    }
+    assert_reduced(&file, "fn f() -> u32 { { g(x) } }");
+}
+
+#[test]
+fn remove_stmts_keeps_the_tail_expression() {
+    let mut file = parse("fn f() -> u32 { a(); let x = b(); c(x); 1 } fn g() { d(); e(); }");
+    remove_stmts::remove_stmts(&mut file, &Cursor::default(), contains(&["c ("]));
+    assert_reduced(&file, "fn f() -> u32 { c(x); 1 } fn g() {}");
+}
+
+#[test]
+fn reduce_generics_only_changes_paths_to_the_owner() {
+    let mut file = parse("
+        struct Foo<T>(T);
+        mod m {
+            pub struct Foo<T>(T);
+            fn g(x: super::Foo<u8>, y: Foo<u16>) {}
+        }
+        fn f(a: Foo<u32>, b: self::Foo<i32>, c: m::Foo<i64>, d: Vec<Foo<u8>>, e: crate::m::Foo<char>, g: ::Foo<bool>) {}");
+    reduce_generics::reduce_generics(&mut file, &Cursor::default(), contains(&["pub struct Foo < T >"]));
+    assert_reduced(&file, "
+        struct Foo(T);
+        mod m {
+            pub struct Foo<T>(T);
+            fn g(x: super::Foo, y: Foo<u16>) {}
+        }
+        fn f(a: Foo, b: self::Foo, c: m::Foo<i64>, d: Vec<Foo>, e: crate::m::Foo<char>, g: ::Foo<bool>) {}");
+}
+
+#[test]
+fn reduce_generics_changes_method_calls_and_paths_through_the_type() {
+    let mut file = parse("
+        struct S;
+        impl S {
+            fn m<T>(&self) {}
+            fn n(&self) { self.m::<u8>(); Self::m::<u8>(self); S::m::<u8>(self); other::m::<u8>(); m::<u8>(); }
+        }
+        fn m<T>() {}");
+    reduce_generics::reduce_generics(&mut file, &Cursor::default(), contains(&["fn m < T > ( ) { }"]));
+    assert_reduced(&file, "
+        struct S;
+        impl S {
+            fn m(&self) {}
+            fn n(&self) { self.m(); Self::m(self); S::m(self); other::m::<u8>(); m::<u8>(); }
+        }
+        fn m<T>() {}");
+}
+
+#[test]
+fn reduce_signatures_only_changes_calls_to_the_function() {
+    let mut file = parse("
+        fn f(a: u8) {}
+        mod m {
+            pub fn f(a: u8) {}
+            fn g() { super::f(1); f(2); }
+        }
+        fn h() { f(3); self::f(4); m::f(5); crate::m::f(6); other::f(7); }");
+    reduce_signatures::reduce_signatures(&mut file, &Cursor::default(), contains(&["pub fn f ( a : u8 )"]));
+    assert_reduced(&file, "
+        fn f() {}
+        mod m {
+            pub fn f(a: u8) {}
+            fn g() { super::f(); f(2); }
+        }
+        fn h() { f(); self::f(); m::f(5); crate::m::f(6); other::f(7); }");
+}
+
+#[test]
+fn reduce_signatures_keeps_the_body_when_removing_the_return_type() {
+    let mut file = parse("fn f() -> ! { std::process::exit(1); } fn g() -> u8 { let x = 1; x }");
+    reduce_signatures::reduce_signatures(&mut file, &Cursor::default(), |_| Ok(()));
+    // only a tail expression turns into a statement
+    assert_reduced(&file, "fn f() { std::process::exit(1); } fn g() { let x = 1; x; }");
+}
+
+#[test]
+fn reduce_signatures_changes_associated_functions_through_their_type() {
+    let mut file = parse("
+        struct S;
+        impl S {
+            fn new(a: u8) -> S { S }
+            fn m(&self, a: u8) {}
+            fn n(&self) { Self::new(1); S::new(2); T::new(3); new(4); self.m(5); S::m(self, 6); }
+        }
+        fn new(a: u8) {}");
+    reduce_signatures::reduce_signatures(&mut file, &Cursor::default(), contains(&["fn new ( a : u8 ) { }", "-> S"]));
+    assert_reduced(&file, "
+        struct S;
+        impl S {
+            fn new() -> S { S }
+            fn m(&self) {}
+            fn n(&self) { Self::new(); S::new(); T::new(3); new(4); self.m(); S::m(self); }
+        }
+        fn new(a: u8) {}");
+}